@@ -1,5 +1,4 @@
-use crate::math::Vec3f;
-use crate::na::zero;
+use crate::math::{Vec3f, Mat4f, transform_point};
 use serde::{Serialize, Deserialize};
 
 pub trait SetPosition {
@@ -7,28 +6,18 @@ pub trait SetPosition {
     fn get_position(&self) -> Vec3f;
 }
 
-#[derive(Serialize, Deserialize)]
+/// Movement along a closed polyline through `control_points` at constant speed
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Animation {
     path_lengts: Vec<f32>,
     control_points: Vec<Vec3f>,
 
-    #[serde(skip)]
-    current: f32,
-
-    #[serde(skip)]
-    i: usize,
-
-    #[serde(skip, default = "zero")]
-    dir: Vec3f,
-
-    #[serde(skip)]
-    pub dirty: bool,
-
-    factor: f32,
+    /// Units per second
+    speed: f32,
 }
 
 impl Animation {
-    pub fn new<T: SetPosition>(object: &T, path: &[Vec3f]) -> Animation {
+    pub fn new<T: SetPosition>(object: &T, path: &[Vec3f], speed: f32) -> Animation {
         let mut control_points = Vec::new();
         let pos = object.get_position();
         control_points.push(pos);
@@ -39,36 +28,131 @@ impl Animation {
         let mut path_lengts: Vec<f32> = path.iter().map(|p| p.norm()).collect();
         let lastpath = &path.iter().fold(Vec3f::new(0., 0., 0.), |acc, p| acc + p);
         path_lengts.push(lastpath.norm());
-        let dir = control_points[1] - pos;
         Animation {
-            dirty: false,
             control_points,
-            current: path_lengts[0],
             path_lengts,
-            i: 0,
-            dir,
-            factor: 1. / 10.,
+            speed,
+        }
+    }
+
+    /// Path through absolute `control_points`, `path_lengts[i]` is the length
+    /// of the segment starting at the point `i`
+    pub fn from_control_points(
+        control_points: Vec<Vec3f>,
+        path_lengts: Vec<f32>,
+        speed: f32,
+    ) -> Animation {
+        Animation {
+            control_points,
+            path_lengts,
+            speed,
         }
     }
 
-    pub fn update(&mut self) -> Vec3f {
-        self.dirty = true;
-        self.current -= self.factor;
-        if self.current < 0. {
-            self.i += 1;
-            if self.i >= self.path_lengts.len() {
-                self.i = 0;
+    /// Same movement with the path moved by `transform`
+    pub fn transformed(&self, transform: &Mat4f) -> Animation {
+        let control_points: Vec<Vec3f> = self
+            .control_points
+            .iter()
+            .map(|p| transform_point(transform, *p))
+            .collect();
+        let n = control_points.len();
+        let path_lengts = (0..n)
+            .map(|i| (control_points[(i + 1) % n] - control_points[i]).norm())
+            .collect();
+        Animation {
+            control_points,
+            path_lengts,
+            speed: self.speed,
+        }
+    }
+
+    /// Every control point has the length of the segment starting at it
+    pub fn is_consistent(&self) -> bool {
+        !self.control_points.is_empty() && self.control_points.len() == self.path_lengts.len()
+    }
+
+    /// Position at `t` seconds since the animation start
+    pub fn position_at(&self, t: f32) -> Vec3f {
+        let total: f32 = self.path_lengts.iter().sum();
+        if total <= 0. {
+            return self.control_points[0];
+        }
+        let mut dist = (t * self.speed).rem_euclid(total);
+        for (i, len) in self.path_lengts.iter().enumerate() {
+            // Repeated control points would divide by zero
+            if *len <= 0. {
+                continue;
             }
-            self.current = self.path_lengts[self.i];
-            if self.i + 1 == self.path_lengts.len() {
-                self.dir = self.control_points[0] - self.control_points[self.i];
-                zero()
-            } else {
-                self.dir = self.control_points[self.i + 1] - self.control_points[self.i];
-                zero()
+            if dist <= *len {
+                let from = self.control_points[i];
+                let to = self.control_points[(i + 1) % self.control_points.len()];
+                return from + (to - from) * (dist / len);
             }
+            dist -= len;
+        }
+        self.control_points[0]
+    }
+}
+
+/// Circular movement around `center` in the plane perpendicular to `axis`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Orbit {
+    pub center: Vec3f,
+    pub axis: Vec3f,
+    pub radius: f32,
+
+    /// Degrees per second
+    pub speed: f32,
+
+    /// Angle at the animation start in degrees
+    #[serde(default)]
+    pub phase: f32,
+}
+
+impl Orbit {
+    pub fn position_at(&self, t: f32) -> Vec3f {
+        let axis = self.axis.normalize();
+        let helper = if axis.x.abs() < 0.9 {
+            Vec3f::new(1., 0., 0.)
         } else {
-            self.dir.normalize() * self.factor
+            Vec3f::new(0., 1., 0.)
+        };
+        let u = axis.cross(&helper).normalize();
+        let v = axis.cross(&u);
+        let angle = (self.phase + self.speed * t).to_radians();
+        self.center + (u * angle.cos() + v * angle.sin()) * self.radius
+    }
+}
+
+/// Movement lights and cameras can follow
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Motion {
+    Path(Animation),
+    Orbit(Orbit),
+}
+
+impl Motion {
+    /// Position at `t` seconds since the animation start
+    pub fn position_at(&self, t: f32) -> Vec3f {
+        match *self {
+            Motion::Path(ref a) => a.position_at(t),
+            Motion::Orbit(ref o) => o.position_at(t),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_control_points() {
+        let a = Vec3f::new(1., 2., 3.);
+        let b = Vec3f::new(4., 2., 3.);
+        let anim = Animation::from_control_points(vec![a, a, b], vec![0., 3., 3.], 1.);
+        assert_eq!(anim.position_at(0.), a);
+        assert_eq!(anim.position_at(1.5), Vec3f::new(2.5, 2., 3.));
+        assert_eq!(anim.position_at(4.5), Vec3f::new(2.5, 2., 3.));
+    }
+}
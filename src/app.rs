@@ -1,22 +1,35 @@
 use crate::fps_counter::FpsCounter;
 use crate::input::InputHandler;
-use crate::raytracer::march;
+use crate::render_settings::SettingsOverrides;
+use crate::renderer::{Renderer, RendererOptions};
 use crate::scene::Scene;
+use crate::watcher::FileWatcher;
 
 use sdl2::event::Event;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::render::TextureAccess;
 
+use std::path::Path;
+use std::time::{Duration, Instant};
+
 const WIDTH: u32 = 512;
 const HEIGHT: u32 = 512;
+/// Range the tile size keys move in
+const MIN_TILE_SIZE: u32 = 4;
+const MAX_TILE_SIZE: u32 = 256;
 
-pub fn run() {
+pub fn run(
+    scene_path: &str,
+    settings: &SettingsOverrides,
+    options: &RendererOptions,
+) -> Result<(), String> {
     let context = sdl2::init().unwrap();
     let video = context.video().unwrap();
     let res = crate::resource::ResourceLoader::new();
     let font_manager = crate::font::FontManager::new();
     let font = font_manager.load(&res, "~res:fonts/courier_code.ttf", 16);
-    println!("Num of cpus: {}", num_cpus::get());
+    let mut renderer = Renderer::new(options);
+    println!("Render threads: {}", renderer.threads());
     let window = video
         .window("demo window", WIDTH, HEIGHT)
         .position_centered()
@@ -29,11 +42,13 @@ pub fn run() {
         .build()
         .unwrap();
 
-    let lines = std::fs::read_to_string("src/scene.ron").expect("Can't read scene.ron");
-    let mut scene: Scene = ron::de::from_str(&lines).unwrap();
-
-    let pixel_count: usize = scene.cam.width as usize * scene.cam.height as usize * 3;
-    let mut pixels: Vec<u8> = vec![0; pixel_count];
+    let mut scene = Scene::load(Path::new(scene_path)).map_err(|e| e.to_string())?;
+    settings.apply(&mut scene.settings);
+    for w in &scene.warnings {
+        eprintln!("{}", w);
+    }
+    let mut watcher = FileWatcher::new(&scene.sources, Duration::from_millis(250));
+    let mut load_error: Option<String> = None;
 
     let tex_creator = canvas.texture_creator();
     let mut texture = tex_creator
@@ -52,10 +67,12 @@ pub fn run() {
 
     let mut fps = FpsCounter::new(100);
     fps.restart();
+    let start = Instant::now();
     let mut input_handler = InputHandler::new();
     let mut first = true;
     'running: loop {
         input_handler.clear();
+        scene.begin_frame();
         for event in pump.poll_iter() {
             match event {
                 Event::Quit { .. } => break 'running,
@@ -64,11 +81,72 @@ pub fn run() {
             }
         }
         input_handler.update(&mut scene.cam);
+        if watcher.changed() || input_handler.reload {
+            match Scene::load(Path::new(scene_path)) {
+                Ok(mut reloaded) => {
+                    settings.apply(&mut reloaded.settings);
+                    for w in &reloaded.warnings {
+                        eprintln!("{}", w);
+                    }
+                    if input_handler.keep_camera {
+                        reloaded.cam = scene.cam;
+                    }
+                    if (reloaded.cam.width, reloaded.cam.height)
+                        != (scene.cam.width, scene.cam.height)
+                    {
+                        texture = tex_creator
+                            .create_texture(
+                                PixelFormatEnum::RGB24,
+                                TextureAccess::Streaming,
+                                reloaded.cam.width,
+                                reloaded.cam.height,
+                            )
+                            .unwrap();
+                    }
+                    watcher.watch(&reloaded.sources);
+                    scene = reloaded;
+                    load_error = None;
+                    first = true;
+                }
+                Err(e) => {
+                    let e = e.to_string();
+                    eprintln!("{}", e);
+                    load_error = Some(e);
+                }
+            }
+        }
+        if input_handler.next_tile_order
+            || input_handler.tile_size_steps != 0
+            || input_handler.thread_steps != 0
+        {
+            if input_handler.next_tile_order {
+                let order = renderer.order().next();
+                renderer.set_order(order);
+            }
+            let mut tile_size = renderer.tile_size();
+            for _ in 0..input_handler.tile_size_steps.abs() {
+                tile_size = if input_handler.tile_size_steps > 0 {
+                    tile_size * 2
+                } else {
+                    tile_size / 2
+                };
+                tile_size = tile_size.clamp(MIN_TILE_SIZE, MAX_TILE_SIZE);
+            }
+            renderer.set_tile_size(tile_size);
+            let threads = (renderer.threads() as i32 + input_handler.thread_steps).max(1);
+            renderer.set_threads(threads as u32);
+            println!(
+                "{} threads, {}px tiles in {} order",
+                renderer.threads(),
+                renderer.tile_size(),
+                renderer.order().name()
+            );
+            first = true;
+        }
         fps.update();
-        scene.update_objects();
+        scene.evaluate_at(start.elapsed().as_secs_f32());
         if first || input_handler.dirty || scene.any_animation_dirty() {
-            let updated = march(&scene);
-            pixels[..updated.len()].clone_from_slice(&updated[..]);
+            let pixels = renderer.render(&scene);
             let _ = texture.update(None, &pixels, scene.cam.width as usize * 3);
             first = false;
         }
@@ -89,6 +167,26 @@ pub fn run() {
         let f_rect = f_surf.rect();
         let f_tex = tex_creator.create_texture_from_surface(&f_surf).unwrap();
         canvas.copy(&f_tex, None, f_rect).unwrap();
+
+        // Scene stays as it was until the file parses again
+        if let Some(ref e) = load_error {
+            let red = sdl2::pixels::Color {
+                r: 255,
+                g: 64,
+                b: 64,
+                a: 255,
+            };
+            let mut y = f_rect.height() as i32;
+            for line in e.lines().filter(|l| !l.is_empty()) {
+                let e_surf = font.render(line).blended_wrapped(red, WIDTH).unwrap();
+                let mut e_rect = e_surf.rect();
+                e_rect.set_y(y);
+                y += e_rect.height() as i32;
+                let e_tex = tex_creator.create_texture_from_surface(&e_surf).unwrap();
+                canvas.copy(&e_tex, None, e_rect).unwrap();
+            }
+        }
         canvas.present();
     }
+    Ok(())
 }
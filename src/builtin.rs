@@ -0,0 +1,282 @@
+//! Reference scenes built into the binary, opened as `~builtin:NAME`
+
+use crate::geometry::{Geometry, Triangle};
+use crate::light::Light;
+use crate::material::MaterialRef;
+use crate::math::{Mat4f, Vec3f};
+use crate::object::Object;
+use crate::scene::{Scene, SceneError};
+use crate::scene_generator::{
+    camera, cornell_box, cuboid, floor, light, material, named, quad, sphere,
+};
+use crate::validation::Issue;
+
+use std::path::Path;
+
+pub const PREFIX: &str = "~builtin:";
+
+pub const NAMES: &str = "cornell, material-ball, shadows, refraction, many-lights";
+
+/// Name of the built-in scene `path` refers to, if it does
+pub fn name(path: &Path) -> Option<&str> {
+    path.to_str()?.strip_prefix(PREFIX)
+}
+
+pub fn load(path: &Path) -> Result<(Scene, Vec<Issue>), SceneError> {
+    let scene = match name(path).unwrap_or("") {
+        "cornell" => cornell_box(),
+        "material-ball" => material_ball(),
+        "shadows" => shadows(),
+        "refraction" => refraction(),
+        "many-lights" => many_lights(),
+        _ => {
+            return Err(SceneError::Import {
+                file: path.to_path_buf(),
+                message: format!("unknown built-in scene, expected one of: {}", NAMES),
+            })
+        }
+    };
+    Ok((scene, Vec::new()))
+}
+
+/// Squares of a `cells` by `cells` checkerboard of side `size` at height `y`,
+/// the dark ones when `odd` is set
+fn checker(name: &str, y: f32, size: f32, cells: usize, odd: bool, mat: MaterialRef) -> Object {
+    let cell = size / cells as f32;
+    let start = -size / 2.;
+    let mut shapes = Vec::new();
+    for i in 0..cells {
+        for j in 0..cells {
+            if ((i + j) % 2 == 1) != odd {
+                continue;
+            }
+            let (x, z) = (start + i as f32 * cell, start + j as f32 * cell);
+            let a = Vec3f::new(x, y, z);
+            let b = Vec3f::new(x + cell, y, z);
+            let c = Vec3f::new(x + cell, y, z + cell);
+            let d = Vec3f::new(x, y, z + cell);
+            shapes.push(Geometry::Triangle(Triangle {
+                a,
+                b,
+                c,
+                colors: None,
+            }));
+            shapes.push(Geometry::Triangle(Triangle {
+                a,
+                b: c,
+                c: d,
+                colors: None,
+            }));
+        }
+    }
+    Object::new(name, shapes, mat, Mat4f::identity())
+}
+
+fn checker_floor(objects: &mut Vec<Object>, size: f32, cells: usize) {
+    objects.push(checker(
+        "floor_light",
+        0.,
+        size,
+        cells,
+        false,
+        named("light"),
+    ));
+    objects.push(checker("floor_dark", 0., size, cells, true, named("dark")));
+}
+
+fn add_floor_materials(scene: &mut Scene) {
+    let grey = |v| Vec3f::new(v, v, v);
+    scene
+        .materials
+        .insert("light".to_string(), material(grey(0.8), 0.1, 5., 0., 0.));
+    scene
+        .materials
+        .insert("dark".to_string(), material(grey(0.25), 0.1, 5., 0., 0.));
+}
+
+/// Ball of the `preview` material on a pedestal over a checkerboard, lit by
+/// a key, a fill and a rim light
+fn material_ball() -> Scene {
+    let mut objects = Vec::new();
+    checker_floor(&mut objects, 40., 8);
+    objects.push(cuboid(
+        "pedestal",
+        Vec3f::new(-3., -2., -3.),
+        Vec3f::new(6., 2., 6.),
+        named("pedestal"),
+    ));
+    objects.push(sphere(
+        "ball",
+        Vec3f::new(0., -6., 0.),
+        4.,
+        named("preview"),
+    ));
+
+    let cam = camera(
+        Vec3f::new(0., -12., 26.),
+        Vec3f::new(0., -5., 0.),
+        18.,
+        128,
+        128,
+    );
+    let lights = vec![
+        light(Vec3f::new(-20., -30., 20.), 0.8),
+        light(Vec3f::new(25., -10., 15.), 0.3),
+        light(Vec3f::new(5., -25., -25.), 0.4),
+    ];
+    let mut scene = Scene::new(cam, objects, lights);
+    add_floor_materials(&mut scene);
+    let preview = material(Vec3f::new(0.8, 0.25, 0.1), 1., 60., 0., 0.);
+    scene.materials.insert("preview".to_string(), preview);
+    let pedestal = material(Vec3f::new(0.4, 0.4, 0.45), 0.2, 10., 0., 0.);
+    scene.materials.insert("pedestal".to_string(), pedestal);
+    scene
+}
+
+/// Floating and resting shapes of every kind under an overhead and an
+/// oblique light, so both shadows can be told apart
+fn shadows() -> Scene {
+    let v = Vec3f::new;
+    let objects = vec![
+        floor("floor", 0., 60., named("floor")),
+        sphere("floating_sphere", v(-9., -7., 0.), 3., named("red")),
+        sphere("resting_sphere", v(-2., -2., 8.), 2., named("blue")),
+        cuboid(
+            "floating_box",
+            v(3., -11., -4.),
+            v(5., 5., 5.),
+            named("green"),
+        ),
+        quad(
+            "floating_square",
+            [
+                v(4., -4., 5.),
+                v(10., -4., 5.),
+                v(10., -4., 11.),
+                v(4., -4., 11.),
+            ],
+            named("yellow"),
+        ),
+    ];
+    let cam = camera(v(0., -25., 40.), v(0., -3., 0.), 22., 128, 128);
+    let lights = vec![light(v(0., -40., 0.), 0.6), light(v(30., -20., 10.), 0.4)];
+    let mut scene = Scene::new(cam, objects, lights);
+    let matte = |color| material(color, 0.2, 10., 0., 0.);
+    scene
+        .materials
+        .insert("floor".to_string(), matte(v(0.7, 0.7, 0.7)));
+    scene
+        .materials
+        .insert("red".to_string(), matte(v(0.8, 0.2, 0.2)));
+    scene
+        .materials
+        .insert("green".to_string(), matte(v(0.2, 0.8, 0.2)));
+    scene
+        .materials
+        .insert("blue".to_string(), matte(v(0.2, 0.3, 0.8)));
+    scene
+        .materials
+        .insert("yellow".to_string(), matte(v(0.8, 0.8, 0.2)));
+    scene
+}
+
+/// Glass sphere and box in front of colored stripes, which they bend
+fn refraction() -> Scene {
+    const STRIPES: usize = 8;
+    let v = Vec3f::new;
+    let mut objects = Vec::new();
+    checker_floor(&mut objects, 60., 12);
+    let width = 40. / STRIPES as f32;
+    for i in 0..STRIPES {
+        let x = -20. + i as f32 * width;
+        let corners = [
+            v(x, 0., -15.),
+            v(x + width, 0., -15.),
+            v(x + width, -30., -15.),
+            v(x, -30., -15.),
+        ];
+        let mat = if i % 2 == 0 {
+            "stripe_red"
+        } else {
+            "stripe_blue"
+        };
+        objects.push(quad(&format!("stripe{}", i), corners, named(mat)));
+    }
+    objects.push(sphere("glass_sphere", v(-4., -6., 0.), 5., named("glass")));
+    objects.push(cuboid(
+        "water_box",
+        v(4., -8., -2.),
+        v(6., 8., 4.),
+        named("water"),
+    ));
+
+    let cam = camera(v(0., -10., 40.), v(0., -7., 0.), 20., 128, 128);
+    let lights = vec![
+        light(v(-10., -40., 30.), 0.7),
+        light(v(20., -20., 30.), 0.4),
+    ];
+    let mut scene = Scene::new(cam, objects, lights);
+    add_floor_materials(&mut scene);
+    let matte = |color| material(color, 0.1, 5., 0., 0.);
+    scene
+        .materials
+        .insert("stripe_red".to_string(), matte(v(0.8, 0.2, 0.2)));
+    scene
+        .materials
+        .insert("stripe_blue".to_string(), matte(v(0.2, 0.3, 0.8)));
+    scene.materials.insert(
+        "glass".to_string(),
+        material(v(1., 1., 1.), 1., 200., 0.1, 1.5),
+    );
+    scene.materials.insert(
+        "water".to_string(),
+        material(v(0.9, 1., 1.), 1., 200., 0.1, 1.33),
+    );
+    scene
+}
+
+/// Spheres under a 6 by 6 grid of dim colored lights, shading cost grows
+/// with the number of lights so this is the one to time light loops with
+fn many_lights() -> Scene {
+    const SIDE: usize = 6;
+    let v = Vec3f::new;
+    let mut objects = vec![floor("floor", 0., 80., named("white"))];
+    for i in 0..3 {
+        for j in 0..3 {
+            let center = v(i as f32 * 10. - 10., -3., j as f32 * 10. - 10.);
+            objects.push(sphere(
+                &format!("sphere_{}_{}", i, j),
+                center,
+                3.,
+                named("white"),
+            ));
+        }
+    }
+
+    let palette = [
+        v(1., 0.3, 0.3),
+        v(0.3, 1., 0.3),
+        v(0.3, 0.3, 1.),
+        v(1., 1., 0.3),
+    ];
+    let mut lights = Vec::new();
+    for i in 0..SIDE {
+        for j in 0..SIDE {
+            let pos = v(i as f32 * 8. - 20., -15., j as f32 * 8. - 20.);
+            let color = palette[(i + j) % palette.len()] * 0.06;
+            lights.push(Light {
+                pos,
+                color,
+                motion: None,
+            });
+        }
+    }
+
+    let cam = camera(v(0., -30., 45.), v(0., -2., 0.), 22., 128, 128);
+    let mut scene = Scene::new(cam, objects, lights);
+    scene.materials.insert(
+        "white".to_string(),
+        material(v(0.9, 0.9, 0.9), 0.5, 30., 0., 0.),
+    );
+    scene
+}
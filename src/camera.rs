@@ -1,5 +1,7 @@
+use crate::animation::{Motion, SetPosition};
 use crate::na::{clamp, Rotation3 as Rot3, Unit};
 use crate::math::Vec3f;
+use crate::sampling;
 
 use serde::{Serialize, Deserialize};
 
@@ -11,6 +13,113 @@ struct Rotate {
     pub y: f32,
 }
 
+/// Shape of the lens aperture, which defines how out of focus highlights look
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Bokeh {
+    #[default]
+    Circle,
+    /// Regular polygon formed by diaphragm blades, `rotation` is in degrees
+    Polygon { blades: u32, rotation: f32 },
+}
+
+impl Bokeh {
+    /// Maps a point of the unit square onto the aperture of unit radius
+    pub fn sample(&self, u: f32, v: f32) -> (f32, f32) {
+        match *self {
+            Bokeh::Circle => sampling::concentric_disk(u, v),
+            Bokeh::Polygon { blades, rotation } => sampling::polygon(u, v, blades, rotation),
+        }
+    }
+}
+
+/// How the image plane is mapped onto primary ray directions
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Projection {
+    /// Pinhole projection, `fov` is the half-angle of the vertical view
+    #[default]
+    Perspective,
+    /// Parallel rays, `height` is the height of the view in world units
+    Orthographic { height: f32 },
+    /// Circular image, `fov` is the angle between the view direction
+    /// and the edge of the image circle
+    Fisheye { mapping: FisheyeMapping },
+    /// 360° around the up axis, perspective along it
+    Cylindrical,
+    /// Full sphere in latitude/longitude layout
+    Equirectangular,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum FisheyeMapping {
+    /// Distance from the image center is proportional to the angle
+    Equidistant,
+    /// Preserves the area of the projected solid angle
+    Equisolid,
+}
+
+/// Stereo pair rendering, both eyes are packed into a single frame
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Stereo {
+    /// Distance between the eyes
+    pub interocular: f32,
+
+    /// Distance from the eyes to the zero parallax plane
+    pub convergence: f32,
+
+    pub method: StereoMethod,
+    pub layout: StereoLayout,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum StereoMethod {
+    /// Parallel eyes with asymmetric frustums meeting at the convergence plane
+    OffAxis,
+    /// Eyes rotated towards the convergence point
+    ToeIn,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum StereoLayout {
+    /// Left eye in the left half of the frame
+    SideBySide,
+    /// Left eye in the top half of the frame
+    TopBottom,
+}
+
+/// Camera placement saved at the beginning of a frame, used for motion blur
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    eye: Vec3f,
+    dir: Vec3f,
+    up: Vec3f,
+}
+
+/// What an animated camera keeps looking at
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Target {
+    Point(Vec3f),
+    /// Object with the given name
+    Object(String),
+}
+
+/// Camera movement, kept apart from `Camera` so it stays a plain value
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CameraAnimation {
+    #[serde(default)]
+    pub motion: Option<Motion>,
+
+    #[serde(default)]
+    pub target: Option<Target>,
+}
+
+fn default_focal_distance() -> f32 {
+    1.0
+}
+
+fn default_samples() -> u32 {
+    1
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Camera {
     /// Camera eye position
@@ -24,7 +133,40 @@ pub struct Camera {
     pub width: u32,
     pub height: u32,
 
+    #[serde(default)]
+    pub projection: Projection,
+
+    /// Renders a left/right eye pair instead of a single view
+    #[serde(default)]
+    pub stereo: Option<Stereo>,
+
+    /// Lens aperture radius, zero makes a pinhole camera
+    #[serde(default)]
+    pub aperture: f32,
+
+    /// Distance from the eye to the plane in focus
+    #[serde(default = "default_focal_distance")]
+    pub focal_distance: f32,
+
+    /// Focus on the object under the screen center every frame
+    #[serde(default)]
+    pub autofocus: bool,
+
+    #[serde(default)]
+    pub bokeh: Bokeh,
+
+    /// Rays traced per pixel
+    #[serde(default = "default_samples")]
+    pub samples: u32,
+
+    /// Fraction of the frame the shutter stays open for, zero disables motion blur
+    #[serde(default)]
+    pub shutter: f32,
+
     angles: Rotate,
+
+    #[serde(skip)]
+    previous: Option<Placement>,
 }
 
 pub struct CamBuilder {
@@ -38,6 +180,15 @@ pub struct CamBuilder {
     fov: f32,
     width: u32,
     height: u32,
+
+    projection: Projection,
+    stereo: Option<Stereo>,
+    aperture: f32,
+    focal_distance: f32,
+    autofocus: bool,
+    bokeh: Bokeh,
+    samples: u32,
+    shutter: f32,
 }
 
 impl CamBuilder {
@@ -50,6 +201,15 @@ impl CamBuilder {
             fov: 0.0,
             width: 0,
             height: 0,
+
+            projection: Projection::Perspective,
+            stereo: None,
+            aperture: 0.0,
+            focal_distance: default_focal_distance(),
+            autofocus: false,
+            bokeh: Bokeh::Circle,
+            samples: default_samples(),
+            shutter: 0.0,
         }
     }
 
@@ -83,6 +243,46 @@ impl CamBuilder {
         self
     }
 
+    pub fn projection(&mut self, projection: Projection) -> &mut CamBuilder {
+        self.projection = projection;
+        self
+    }
+
+    pub fn stereo(&mut self, stereo: Option<Stereo>) -> &mut CamBuilder {
+        self.stereo = stereo;
+        self
+    }
+
+    pub fn aperture(&mut self, aperture: f32) -> &mut CamBuilder {
+        self.aperture = aperture;
+        self
+    }
+
+    pub fn focal_distance(&mut self, focal_distance: f32) -> &mut CamBuilder {
+        self.focal_distance = focal_distance;
+        self
+    }
+
+    pub fn autofocus(&mut self, autofocus: bool) -> &mut CamBuilder {
+        self.autofocus = autofocus;
+        self
+    }
+
+    pub fn bokeh(&mut self, bokeh: Bokeh) -> &mut CamBuilder {
+        self.bokeh = bokeh;
+        self
+    }
+
+    pub fn samples(&mut self, samples: u32) -> &mut CamBuilder {
+        self.samples = samples;
+        self
+    }
+
+    pub fn shutter(&mut self, shutter: f32) -> &mut CamBuilder {
+        self.shutter = shutter;
+        self
+    }
+
     pub fn build(&self) -> Camera {
         let dir = self.center - self.eye;
         let y = Vec3f::new(0.0, 0.0, 1.0).dot(&dir).acos().to_degrees();
@@ -93,12 +293,50 @@ impl CamBuilder {
             fov: self.fov,
             width: self.width,
             height: self.height,
+            projection: self.projection,
+            stereo: self.stereo,
+            aperture: self.aperture,
+            focal_distance: self.focal_distance,
+            autofocus: self.autofocus,
+            bokeh: self.bokeh,
+            samples: self.samples,
+            shutter: self.shutter,
             angles: Rotate { x: 0.0, y },
+            previous: None,
         }
     }
 }
 
 impl Camera {
+    /// Remembers the current placement as the one the next frame starts from
+    pub fn begin_frame(&mut self) {
+        self.previous = Some(Placement {
+            eye: self.eye,
+            dir: self.dir,
+            up: self.up,
+        });
+    }
+
+    /// Maps a point `u` of the unit interval onto the moment within the frame
+    /// the shutter is open at, the shutter closes at the end of the frame
+    pub fn shutter_time(&self, u: f32) -> f32 {
+        1.0 - self.shutter * (1.0 - u)
+    }
+
+    /// Camera moved to the given moment between the previous and the current frame
+    pub fn at(&self, time: f32) -> Camera {
+        let mut cam = *self;
+        if let Some(prev) = self.previous {
+            if time < 1.0 {
+                let s = 1.0 - time;
+                cam.eye = self.eye + (prev.eye - self.eye) * s;
+                cam.dir = (self.dir + (prev.dir - self.dir) * s).normalize();
+                cam.up = (self.up + (prev.up - self.up) * s).normalize();
+            }
+        }
+        cam
+    }
+
     pub fn yaw(&mut self, angle: f32) {
         self.angles.y -= angle;
         if self.angles.y < 0.0 {
@@ -129,6 +367,21 @@ impl Camera {
         self.up = rot_y * rot_x * Vec3f::new(0.0, -1.0, 0.0);
     }
 
+    /// Turns the camera towards the point, keeping the up vector upright
+    pub fn look_at(&mut self, target: Vec3f) {
+        let dir = target - self.eye;
+        if dir.norm() == 0.0 {
+            return;
+        }
+        let dir = dir.normalize();
+        self.angles.x = clamp(-dir.y.asin().to_degrees(), -89.0, 89.0);
+        self.angles.y = dir.x.atan2(dir.z).to_degrees();
+        if self.angles.y < 0.0 {
+            self.angles.y += 360.0;
+        }
+        self.update();
+    }
+
     pub fn mov_fwd(&mut self, dist: f32) {
         let dir = self.dir;
         self.eye += dir * dist;
@@ -141,3 +394,13 @@ impl Camera {
         self.eye += side * dist;
     }
 }
+
+impl SetPosition for Camera {
+    fn set_position(&mut self, pos: Vec3f) {
+        self.eye = pos;
+    }
+
+    fn get_position(&self) -> Vec3f {
+        self.eye
+    }
+}
@@ -0,0 +1,338 @@
+use crate::render_settings::SettingsOverrides;
+use crate::renderer::{RendererOptions, TileOrder};
+use crate::scene_generator::Template;
+use crate::sequence::{Encoder, SequenceOptions};
+
+use std::path::PathBuf;
+
+pub const USAGE: &str = "Usage:
+    rt [--scene FILE] [SETTINGS]
+        Interactive viewer
+
+    Scenes can also be given as ~builtin:NAME, where NAME is one of cornell,
+    material-ball, shadows, refraction, many-lights
+
+    rt render [--scene FILE] [--out DIR] [--start N] [--end N] [--fps F]
+              [--y4m FILE | --pipe COMMAND] [SETTINGS]
+        Renders frames START..END into DIR/frame_NNNNN.ppm, skipping existing ones.
+        --y4m also writes an uncompressed YUV4MPEG2 video, --pipe feeds raw RGB24
+        frames to COMMAND, e.g. \"ffmpeg -f rawvideo -pix_fmt rgb24 -s 128x128 -r 30 -i - out.mp4\"
+
+    SETTINGS override the render settings of the scene and set up the renderer:
+        --max-depth N  reflections and refractions together followed along a ray path
+        --max-reflection-depth N, --max-refraction-depth N  limits for each of them
+        --max-diffuse-depth N  reflections and refractions after which hit points
+            still get direct light
+        --ray-offset D  distance secondary rays start off surfaces
+        --threads N  render threads, one per CPU by default
+        --tile-size N  side of the square tiles the frame is split into, 32 by default
+        --tile-order ORDER  order tiles are rendered in: scanline, spiral (the
+            default, from the center out) or hilbert
+
+    rt migrate FILE... [--out FILE]
+        Rewrites scene files of older versions in the current format, in place
+        unless --out is given for a single file
+
+    rt import FILE [--out FILE]
+        Converts a glTF 2.0 (.gltf or .glb) or PBRT-v3 (.pbrt) file into a scene,
+        written next to it with the .ron extension unless --out is given. The
+        viewer and render also open these files directly
+
+    rt export FILE --out FILE [--time T]
+        Writes the scene as it is T seconds into its animation (0 by default)
+        to OBJ with an MTL file next to it (.obj), or glTF 2.0 (.gltf with a .bin
+        buffer, or .glb), spheres and boxes are tessellated
+
+    rt generate TEMPLATE [--count N] [--seed S] [--out FILE]
+        Writes a generated scene to FILE, or prints it. Templates: spheres (N random
+        spheres on a floor, 40 by default), cornell (Cornell box), materials (N by N
+        grid of reflection and shininess, 5 by default), stress (N random objects,
+        1000 by default). The same seed (0 by default) gives the same scene
+
+    rt compare IMAGE IMAGE [--out FILE] [--max E]
+        Prints MSE, PSNR, SSIM and the largest per pixel difference of two PNG, PPM
+        or EXR images of the same size, --out writes a false color map of the per
+        pixel RMS difference (.png or .ppm), black at 0 and pale yellow at E and
+        above, 1 by default";
+
+const DEFAULT_SCENE: &str = "src/scene.ron";
+
+pub enum Command {
+    View {
+        scene: String,
+        settings: SettingsOverrides,
+        renderer: RendererOptions,
+    },
+    Render(SequenceOptions),
+    Migrate {
+        files: Vec<String>,
+        out: Option<String>,
+    },
+    Import {
+        file: String,
+        out: Option<String>,
+    },
+    Export {
+        file: String,
+        out: String,
+        time: f32,
+    },
+    Generate {
+        template: Template,
+        count: Option<usize>,
+        seed: u64,
+        out: Option<String>,
+    },
+    Compare {
+        first: String,
+        second: String,
+        out: Option<String>,
+        max: f32,
+    },
+}
+
+/// Walks over `--flag value` pairs
+struct Flags<'a> {
+    args: &'a [String],
+    pos: usize,
+}
+
+impl<'a> Flags<'a> {
+    fn new(args: &'a [String]) -> Flags<'a> {
+        Flags { args, pos: 0 }
+    }
+
+    fn next_flag(&mut self) -> Option<&'a str> {
+        let flag = self.args.get(self.pos)?;
+        self.pos += 1;
+        Some(flag)
+    }
+
+    fn value(&mut self, flag: &str) -> Result<&'a str, String> {
+        let value = self
+            .args
+            .get(self.pos)
+            .ok_or_else(|| format!("{} requires a value", flag))?;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    fn parse<T: std::str::FromStr>(&mut self, flag: &str) -> Result<T, String> {
+        let value = self.value(flag)?;
+        value
+            .parse()
+            .map_err(|_| format!("Invalid value for {}: {}", flag, value))
+    }
+}
+
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    match args.first().map(String::as_str) {
+        Some("render") => parse_render(&args[1..]),
+        Some("migrate") => parse_migrate(&args[1..]),
+        Some("import") => parse_import(&args[1..]),
+        Some("export") => parse_export(&args[1..]),
+        Some("generate") => parse_generate(&args[1..]),
+        Some("compare") => parse_compare(&args[1..]),
+        _ => parse_view(args),
+    }
+}
+
+/// Reads the value of a render settings or renderer flag, false if `flag` isn't one
+fn settings_flag(
+    flag: &str,
+    flags: &mut Flags,
+    settings: &mut SettingsOverrides,
+    renderer: &mut RendererOptions,
+) -> Result<bool, String> {
+    match flag {
+        "--max-depth" => settings.max_depth = Some(flags.parse(flag)?),
+        "--max-reflection-depth" => settings.max_reflection_depth = Some(flags.parse(flag)?),
+        "--max-refraction-depth" => settings.max_refraction_depth = Some(flags.parse(flag)?),
+        "--max-diffuse-depth" => settings.max_diffuse_depth = Some(flags.parse(flag)?),
+        "--ray-offset" => {
+            let offset: f32 = flags.parse(flag)?;
+            if offset.is_nan() || offset < 0. {
+                return Err("--ray-offset can't be negative".to_string());
+            }
+            settings.ray_offset = Some(offset);
+        }
+        "--threads" => renderer.threads = flags.parse(flag)?,
+        "--tile-size" => {
+            renderer.tile_size = flags.parse(flag)?;
+            if renderer.tile_size == 0 {
+                return Err("--tile-size must be positive".to_string());
+            }
+        }
+        "--tile-order" => renderer.order = flags.value(flag)?.parse::<TileOrder>()?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn parse_view(args: &[String]) -> Result<Command, String> {
+    let mut scene = DEFAULT_SCENE.to_string();
+    let mut settings = SettingsOverrides::default();
+    let mut renderer = RendererOptions::default();
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        match flag {
+            "--scene" => scene = flags.value(flag)?.to_string(),
+            _ if settings_flag(flag, &mut flags, &mut settings, &mut renderer)? => (),
+            _ => return Err(format!("Unknown argument: {}", flag)),
+        }
+    }
+    Ok(Command::View {
+        scene,
+        settings,
+        renderer,
+    })
+}
+
+fn parse_render(args: &[String]) -> Result<Command, String> {
+    let mut opts = SequenceOptions {
+        scene: DEFAULT_SCENE.to_string(),
+        out_dir: PathBuf::from("frames"),
+        start: 0,
+        end: 100,
+        fps: 30.,
+        encoder: None,
+        settings: SettingsOverrides::default(),
+        renderer: RendererOptions::default(),
+    };
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        match flag {
+            "--scene" => opts.scene = flags.value(flag)?.to_string(),
+            "--out" => opts.out_dir = PathBuf::from(flags.value(flag)?),
+            "--start" => opts.start = flags.parse(flag)?,
+            "--end" => opts.end = flags.parse(flag)?,
+            "--fps" => opts.fps = flags.parse(flag)?,
+            "--y4m" => opts.encoder = Some(Encoder::Y4m(PathBuf::from(flags.value(flag)?))),
+            "--pipe" => opts.encoder = Some(Encoder::Pipe(flags.value(flag)?.to_string())),
+            _ if settings_flag(flag, &mut flags, &mut opts.settings, &mut opts.renderer)? => (),
+            _ => return Err(format!("Unknown argument: {}", flag)),
+        }
+    }
+    if !(opts.fps > 0. && opts.fps.is_finite()) {
+        return Err("--fps must be positive".to_string());
+    }
+    if opts.end <= opts.start {
+        return Err("--end must be after --start".to_string());
+    }
+    Ok(Command::Render(opts))
+}
+
+fn parse_migrate(args: &[String]) -> Result<Command, String> {
+    let mut files = Vec::new();
+    let mut out = None;
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        match flag {
+            "--out" => out = Some(flags.value(flag)?.to_string()),
+            _ if flag.starts_with("--") => return Err(format!("Unknown argument: {}", flag)),
+            _ => files.push(flag.to_string()),
+        }
+    }
+    if files.is_empty() {
+        return Err("No scene files given".to_string());
+    }
+    if out.is_some() && files.len() > 1 {
+        return Err("--out can only be used with a single file".to_string());
+    }
+    Ok(Command::Migrate { files, out })
+}
+
+fn parse_import(args: &[String]) -> Result<Command, String> {
+    let mut file = None;
+    let mut out = None;
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        match flag {
+            "--out" => out = Some(flags.value(flag)?.to_string()),
+            _ if flag.starts_with("--") || file.is_some() => {
+                return Err(format!("Unknown argument: {}", flag))
+            }
+            _ => file = Some(flag.to_string()),
+        }
+    }
+    let file = file.ok_or("No file to import given")?;
+    Ok(Command::Import { file, out })
+}
+
+fn parse_export(args: &[String]) -> Result<Command, String> {
+    let mut file = None;
+    let mut out = None;
+    let mut time = 0.;
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        match flag {
+            "--out" => out = Some(flags.value(flag)?.to_string()),
+            "--time" => time = flags.parse(flag)?,
+            _ if flag.starts_with("--") || file.is_some() => {
+                return Err(format!("Unknown argument: {}", flag))
+            }
+            _ => file = Some(flag.to_string()),
+        }
+    }
+    let file = file.ok_or("No scene to export given")?;
+    let out = out.ok_or("--out is required")?;
+    Ok(Command::Export { file, out, time })
+}
+
+fn parse_generate(args: &[String]) -> Result<Command, String> {
+    let mut template = None;
+    let mut count = None;
+    let mut seed = 0;
+    let mut out = None;
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        match flag {
+            "--count" => count = Some(flags.parse(flag)?),
+            "--seed" => seed = flags.parse(flag)?,
+            "--out" => out = Some(flags.value(flag)?.to_string()),
+            _ if flag.starts_with("--") || template.is_some() => {
+                return Err(format!("Unknown argument: {}", flag))
+            }
+            _ => template = Some(flag.parse()?),
+        }
+    }
+    let template = template
+        .ok_or_else(|| format!("No template given, expected one of: {}", Template::NAMES))?;
+    Ok(Command::Generate {
+        template,
+        count,
+        seed,
+        out,
+    })
+}
+
+fn parse_compare(args: &[String]) -> Result<Command, String> {
+    let mut images = Vec::new();
+    let mut out = None;
+    let mut max: f32 = 1.;
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        match flag {
+            "--out" => out = Some(flags.value(flag)?.to_string()),
+            "--max" => max = flags.parse(flag)?,
+            _ if flag.starts_with("--") || images.len() == 2 => {
+                return Err(format!("Unknown argument: {}", flag))
+            }
+            _ => images.push(flag.to_string()),
+        }
+    }
+    if images.len() != 2 {
+        return Err("Two images to compare are needed".to_string());
+    }
+    if !(max > 0. && max.is_finite()) {
+        return Err("--max must be positive".to_string());
+    }
+    let second = images.pop().unwrap();
+    let first = images.pop().unwrap();
+    Ok(Command::Compare {
+        first,
+        second,
+        out,
+        max,
+    })
+}
@@ -0,0 +1,268 @@
+//! Differences between two renders, for `rt compare`
+
+use crate::image_io::{read_ppm, write_ppm};
+
+use std::path::Path;
+
+/// RGB image with display values from 0 to 1, rows top to bottom
+struct Picture {
+    width: u32,
+    height: u32,
+    pixels: Vec<f32>,
+}
+
+pub struct Metrics {
+    /// Mean squared channel difference
+    pub mse: f64,
+    /// Peak signal to noise ratio in dB, infinite for equal images
+    pub psnr: f64,
+    /// Mean structural similarity of the luminance, 1 for equal images
+    pub ssim: f64,
+}
+
+/// Linear values to the sRGB curve, so EXR renders compare with 8-bit ones
+fn encode_srgb(v: f32) -> f32 {
+    let v = v.clamp(0., 1.);
+    if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1. / 2.4) - 0.055
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case(extension),
+        None => false,
+    }
+}
+
+fn load(path: &Path) -> Result<Picture, String> {
+    if has_extension(path, "ppm") {
+        let image = read_ppm(path).map_err(|e| e.to_string())?;
+        return Ok(Picture {
+            width: image.width,
+            height: image.height,
+            pixels: image.pixels.iter().map(|&v| f32::from(v) / 255.).collect(),
+        });
+    }
+    let image = image::open(path)
+        .map_err(|e| format!("Can't read {}: {}", path.display(), e))?
+        .to_rgb32f();
+    let (width, height) = (image.width(), image.height());
+    let mut pixels = image.into_raw();
+    // EXR holds linear light, the other formats are already encoded
+    if has_extension(path, "exr") {
+        pixels.iter_mut().for_each(|v| *v = encode_srgb(*v));
+    }
+    Ok(Picture {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn luminance(p: &Picture) -> Vec<f64> {
+    p.pixels
+        .chunks(3)
+        .map(|c| f64::from(0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2]))
+        .collect()
+}
+
+/// Gaussian blur with sigma 1.5 over 11 pixels, edges are extended
+fn blur(values: &[f64], width: usize, height: usize) -> Vec<f64> {
+    const RADIUS: i64 = 5;
+    let weights: Vec<f64> = (-RADIUS..=RADIUS)
+        .map(|i| (-(i * i) as f64 / (2. * 1.5 * 1.5)).exp())
+        .collect();
+    let total: f64 = weights.iter().sum();
+    let pass = |src: &[f64], horizontal: bool| -> Vec<f64> {
+        let mut out = vec![0.; src.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = 0.;
+                for (k, w) in weights.iter().enumerate() {
+                    let d = k as i64 - RADIUS;
+                    let (sx, sy) = if horizontal {
+                        ((x as i64 + d).clamp(0, width as i64 - 1) as usize, y)
+                    } else {
+                        (x, (y as i64 + d).clamp(0, height as i64 - 1) as usize)
+                    };
+                    sum += w * src[sy * width + sx];
+                }
+                out[y * width + x] = sum / total;
+            }
+        }
+        out
+    };
+    pass(&pass(values, true), false)
+}
+
+fn ssim(a: &Picture, b: &Picture) -> f64 {
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+    let (w, h) = (a.width as usize, a.height as usize);
+    let (x, y) = (luminance(a), luminance(b));
+    let product = |p: &[f64], q: &[f64]| p.iter().zip(q).map(|(p, q)| p * q).collect::<Vec<f64>>();
+    let (mx, my) = (blur(&x, w, h), blur(&y, w, h));
+    let (xx, yy, xy) = (
+        blur(&product(&x, &x), w, h),
+        blur(&product(&y, &y), w, h),
+        blur(&product(&x, &y), w, h),
+    );
+    let sum: f64 = (0..w * h)
+        .map(|i| {
+            let (vx, vy, cov) = (
+                xx[i] - mx[i] * mx[i],
+                yy[i] - my[i] * my[i],
+                xy[i] - mx[i] * my[i],
+            );
+            ((2. * mx[i] * my[i] + C1) * (2. * cov + C2))
+                / ((mx[i] * mx[i] + my[i] * my[i] + C1) * (vx + vy + C2))
+        })
+        .sum();
+    sum / (w * h) as f64
+}
+
+fn metrics(a: &Picture, b: &Picture) -> Metrics {
+    let squared: f64 = a
+        .pixels
+        .iter()
+        .zip(&b.pixels)
+        .map(|(a, b)| f64::from(a - b).powi(2))
+        .sum();
+    let mse = squared / a.pixels.len().max(1) as f64;
+    let psnr = if mse == 0. {
+        f64::INFINITY
+    } else {
+        10. * (1. / mse).log10()
+    };
+    Metrics {
+        mse,
+        psnr,
+        ssim: ssim(a, b),
+    }
+}
+
+/// Magma-like color ramp from black over purple and orange to pale yellow
+fn false_color(t: f32) -> [u8; 3] {
+    const STOPS: [[f32; 3]; 5] = [
+        [0., 0., 4.],
+        [81., 18., 124.],
+        [183., 55., 121.],
+        [252., 137., 97.],
+        [252., 253., 191.],
+    ];
+    let scaled = t.clamp(0., 1.) * (STOPS.len() - 1) as f32;
+    let i = (scaled as usize).min(STOPS.len() - 2);
+    let f = scaled - i as f32;
+    let mut color = [0; 3];
+    for (k, c) in color.iter_mut().enumerate() {
+        *c = (STOPS[i][k] + (STOPS[i + 1][k] - STOPS[i][k]) * f).round() as u8;
+    }
+    color
+}
+
+/// Per pixel RMS channel difference, from 0 to 1
+fn errors(a: &Picture, b: &Picture) -> Vec<f32> {
+    a.pixels
+        .chunks(3)
+        .zip(b.pixels.chunks(3))
+        .map(|(p, q)| (p.iter().zip(q).map(|(p, q)| (p - q) * (p - q)).sum::<f32>() / 3.).sqrt())
+        .collect()
+}
+
+/// Errors in false color, `range` and above at the top of the ramp so maps
+/// made with the same range can be compared with each other
+fn write_heatmap(a: &Picture, errors: &[f32], range: f32, path: &Path) -> Result<(), String> {
+    let pixels: Vec<u8> = errors
+        .iter()
+        .flat_map(|&e| false_color(e / range))
+        .collect();
+    let written = if has_extension(path, "ppm") {
+        write_ppm(path, a.width, a.height, &pixels).map_err(|e| e.to_string())
+    } else {
+        image::save_buffer(
+            path,
+            &pixels,
+            a.width,
+            a.height,
+            image::ExtendedColorType::Rgb8,
+        )
+        .map_err(|e| e.to_string())
+    };
+    written.map_err(|e| format!("Can't write {}: {}", path.display(), e))
+}
+
+/// Prints the metrics of two images and writes the error heatmap to `out`
+/// if given, with errors of `range` and more at the top of its ramp
+pub fn compare(first: &str, second: &str, out: Option<&str>, range: f32) -> Result<(), String> {
+    let a = load(Path::new(first))?;
+    let b = load(Path::new(second))?;
+    if (a.width, a.height) != (b.width, b.height) {
+        return Err(format!(
+            "Image sizes differ: {}x{} and {}x{}",
+            a.width, a.height, b.width, b.height
+        ));
+    }
+    let m = metrics(&a, &b);
+    let errors = errors(&a, &b);
+    println!("MSE   {:.6}", m.mse);
+    println!("PSNR  {:.2} dB", m.psnr);
+    println!("SSIM  {:.4}", m.ssim);
+    println!("Max   {:.4}", errors.iter().cloned().fold(0., f32::max));
+    if let Some(out) = out {
+        write_heatmap(&a, &errors, range, Path::new(out))?;
+        println!(
+            "Heatmap written to {}, errors of {} and more at the top",
+            out, range
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat(value: f32) -> Picture {
+        Picture {
+            width: 16,
+            height: 16,
+            pixels: vec![value; 16 * 16 * 3],
+        }
+    }
+
+    #[test]
+    fn identical_images() {
+        let mut a = flat(0.);
+        for (i, v) in a.pixels.iter_mut().enumerate() {
+            *v = (i % 7) as f32 / 7.;
+        }
+        let b = Picture {
+            width: a.width,
+            height: a.height,
+            pixels: a.pixels.clone(),
+        };
+        let m = metrics(&a, &b);
+        assert_eq!(m.mse, 0.);
+        assert_eq!(m.psnr, f64::INFINITY);
+        assert!((m.ssim - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn known_offset() {
+        let m = metrics(&flat(0.5), &flat(0.6));
+        assert!((m.mse - 0.01).abs() < 1e-6);
+        assert!((m.psnr - 20.).abs() < 1e-3);
+        assert!(m.ssim < 1.);
+    }
+
+    #[test]
+    fn heatmap_range_is_fixed() {
+        let e = errors(&flat(0.5), &flat(0.6));
+        assert!(e.iter().all(|&e| (e - 0.1).abs() < 1e-6));
+        assert_eq!(false_color(0.), [0, 0, 4]);
+        assert_eq!(false_color(2.), [252, 253, 191]);
+    }
+}
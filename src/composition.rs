@@ -0,0 +1,186 @@
+//! Scenes split across files: included fragments and prefab instances
+
+use crate::light::Light;
+use crate::material::{MaterialLibrary, MaterialRef};
+use crate::math::Mat4f;
+use crate::object::Object;
+use crate::scene::{parse_error, read, resolve_path, Scene, SceneError};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Group of objects placed together, object transforms are relative to the instance
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Prefab {
+    pub objects: Vec<Object>,
+}
+
+impl Prefab {
+    /// True if an object of the prefab, at any depth, has the name
+    pub fn contains(&self, name: &str) -> bool {
+        fn find(objects: &[Object], name: &str) -> bool {
+            objects
+                .iter()
+                .any(|o| o.name == name || find(&o.children, name))
+        }
+        find(&self.objects, name)
+    }
+}
+
+pub type Prefabs = BTreeMap<String, Prefab>;
+
+/// Copy of a prefab placed in the scene
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Instance {
+    /// Objects of the instance are named `name/object`
+    pub name: String,
+    pub prefab: String,
+    #[serde(default = "Mat4f::identity")]
+    pub transform: Mat4f,
+    /// Material for every object of the prefab
+    #[serde(default)]
+    pub mat: Option<MaterialRef>,
+    /// Materials of single objects by their name in the prefab, take precedence over `mat`
+    #[serde(default)]
+    pub overrides: BTreeMap<String, MaterialRef>,
+}
+
+/// Part of a scene kept in its own file, paths in it are relative to that file
+#[derive(Deserialize)]
+struct Fragment {
+    #[serde(default)]
+    includes: Vec<String>,
+    #[serde(default)]
+    material_libraries: Vec<String>,
+    #[serde(default)]
+    materials: MaterialLibrary,
+    #[serde(default)]
+    prefabs: Prefabs,
+    #[serde(default)]
+    objects: Vec<Object>,
+    #[serde(default)]
+    instances: Vec<Instance>,
+    #[serde(default)]
+    lights: Vec<Light>,
+}
+
+fn parent(file: &Path) -> &Path {
+    file.parent().unwrap_or_else(|| Path::new(""))
+}
+
+fn canonical(file: &Path) -> PathBuf {
+    file.canonicalize().unwrap_or_else(|_| file.to_path_buf())
+}
+
+/// Makes mesh paths of the objects and their children relative to `dir` usable
+/// from anywhere, so they still point to the right file once the objects move
+/// into the including scene
+fn rebase_meshes(objects: &mut [Object], dir: &Path) {
+    for obj in objects {
+        if let Some(ref mut mesh) = obj.mesh {
+            *mesh = resolve_path(dir, mesh).to_string_lossy().into_owned();
+        }
+        rebase_meshes(&mut obj.children, dir);
+    }
+}
+
+/// Pulls the fragments `scene` read from `file` includes into it, returns
+/// every material library file met on the way
+pub fn include(scene: &mut Scene, file: &Path) -> Result<Vec<PathBuf>, SceneError> {
+    let dir = parent(file);
+    let mut libraries = scene
+        .material_libraries
+        .iter()
+        .map(|l| resolve_path(dir, l))
+        .collect();
+    rebase_meshes(&mut scene.objects, dir);
+    for prefab in scene.prefabs.values_mut() {
+        rebase_meshes(&mut prefab.objects, dir);
+    }
+    let mut stack = vec![canonical(file)];
+    for inc in scene.includes.clone() {
+        include_fragment(scene, &resolve_path(dir, &inc), &mut stack, &mut libraries)?;
+    }
+    Ok(libraries)
+}
+
+/// Objects, lights and instances are appended, materials and prefabs
+/// already defined by the including file are kept
+fn include_fragment(
+    scene: &mut Scene,
+    file: &Path,
+    stack: &mut Vec<PathBuf>,
+    libraries: &mut Vec<PathBuf>,
+) -> Result<(), SceneError> {
+    let id = canonical(file);
+    if stack.contains(&id) {
+        return Err(SceneError::IncludeCycle {
+            file: file.to_path_buf(),
+        });
+    }
+    let text = read(file)?;
+    let mut fragment: Fragment = ron::de::from_str(&text).map_err(|e| parse_error(file, e))?;
+    scene.sources.push(file.to_path_buf());
+
+    let dir = parent(file);
+    rebase_meshes(&mut fragment.objects, dir);
+    for prefab in fragment.prefabs.values_mut() {
+        rebase_meshes(&mut prefab.objects, dir);
+    }
+    libraries.extend(
+        fragment
+            .material_libraries
+            .iter()
+            .map(|l| resolve_path(dir, l)),
+    );
+    for (name, mat) in fragment.materials {
+        scene.materials.entry(name).or_insert(mat);
+    }
+    for (name, prefab) in fragment.prefabs {
+        scene.prefabs.entry(name).or_insert(prefab);
+    }
+    scene.objects.extend(fragment.objects);
+    scene.instances.extend(fragment.instances);
+    scene.lights.extend(fragment.lights);
+
+    stack.push(id);
+    for inc in &fragment.includes {
+        include_fragment(scene, &resolve_path(dir, inc), stack, libraries)?;
+    }
+    stack.pop();
+    Ok(())
+}
+
+/// Copy of a prefab object and its children named and dressed for the instance
+fn instantiate(obj: &Object, inst: &Instance) -> Object {
+    let mut copy = obj.clone();
+    copy.name = format!("{}/{}", inst.name, obj.name);
+    if let Some(mat) = inst.overrides.get(&obj.name).or(inst.mat.as_ref()) {
+        copy.material = mat.clone();
+    }
+    copy.children = obj.children.iter().map(|c| instantiate(c, inst)).collect();
+    copy
+}
+
+/// Adds the objects of every instance to the scene, instances of unknown
+/// prefabs are left to validation
+pub fn expand_instances(scene: &mut Scene) {
+    for inst in &scene.instances {
+        let prefab = match scene.prefabs.get(&inst.prefab) {
+            Some(prefab) => prefab,
+            None => continue,
+        };
+        for obj in &prefab.objects {
+            // Children are placed relative to their parent and need no change
+            let mut copy = instantiate(obj, inst);
+            copy.transform = inst.transform * obj.transform;
+            copy.animation = obj
+                .animation
+                .as_ref()
+                .map(|a| a.transformed(&inst.transform));
+            scene.objects.push(copy);
+        }
+    }
+}
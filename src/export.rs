@@ -0,0 +1,571 @@
+//! Scene export for other tools: OBJ with an MTL library, and glTF 2.0 as
+//! `.gltf` with a `.bin` buffer or a single `.glb`. Spheres and boxes are
+//! tessellated and every object is written in world space.
+
+use crate::camera::Projection;
+use crate::geometry::Geometry;
+use crate::material::{shininess_to_roughness, Material, MaterialRef};
+use crate::math::{inverse, transform_point, translation, Mat4f, Vec3f};
+use crate::object::Object;
+use crate::scene::Scene;
+
+use gltf::json;
+use gltf::json::validation::Checked::Valid;
+use gltf::json::validation::USize64;
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Latitude bands and longitude slices of tessellated spheres
+const SPHERE_RINGS: usize = 16;
+const SPHERE_SEGMENTS: usize = 32;
+
+/// The renderer world is left handed with y pointing down, the exported
+/// formats are right handed with y pointing up, flipping y converts
+fn y_up(v: Vec3f) -> Vec3f {
+    Vec3f::new(v.x, -v.y, v.z)
+}
+
+#[derive(Clone, Copy)]
+struct Vertex {
+    pos: Vec3f,
+    normal: Vec3f,
+    color: Vec3f,
+}
+
+/// Triangles of one object in the coordinates of the exported file,
+/// three vertices each
+struct Mesh {
+    name: String,
+    material: String,
+    vertices: Vec<Vertex>,
+    /// Some triangles have vertex colors, the others are white
+    colored: bool,
+}
+
+impl Mesh {
+    /// Adds a triangle given in world space, `normals` point to the outside
+    fn push(&mut self, corners: [Vec3f; 3], normals: [Vec3f; 3], colors: [Vec3f; 3]) {
+        let mut v: Vec<Vertex> = (0..3)
+            .map(|i| Vertex {
+                pos: y_up(corners[i]),
+                normal: y_up(normals[i]),
+                color: colors[i],
+            })
+            .collect();
+        // Flipping y mirrors the triangles, the winding has to follow the normals again
+        let face = (v[1].pos - v[0].pos).cross(&(v[2].pos - v[0].pos));
+        if face.dot(&(v[0].normal + v[1].normal + v[2].normal)) < 0. {
+            v.swap(1, 2);
+        }
+        self.vertices.extend(v);
+    }
+
+    fn push_flat(&mut self, corners: [Vec3f; 3], normal: Vec3f) {
+        self.push(corners, [normal; 3], [Vec3f::repeat(1.); 3]);
+    }
+
+    fn sphere(&mut self, center: Vec3f, radius: f32) {
+        let point = |ring: usize, segment: usize| {
+            let theta = std::f32::consts::PI * ring as f32 / SPHERE_RINGS as f32;
+            let phi = 2. * std::f32::consts::PI * segment as f32 / SPHERE_SEGMENTS as f32;
+            Vec3f::new(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            )
+        };
+        let white = [Vec3f::repeat(1.); 3];
+        for ring in 0..SPHERE_RINGS {
+            for segment in 0..SPHERE_SEGMENTS {
+                let a = point(ring, segment);
+                let b = point(ring + 1, segment);
+                let c = point(ring + 1, segment + 1);
+                let d = point(ring, segment + 1);
+                // Bands next to the poles have one corner in the pole
+                if ring + 1 < SPHERE_RINGS {
+                    self.push([a, b, c].map(|p| center + p * radius), [a, b, c], white);
+                }
+                if ring > 0 {
+                    self.push([a, c, d].map(|p| center + p * radius), [a, c, d], white);
+                }
+            }
+        }
+    }
+
+    /// Box from the origin to `extent` placed by `transform`
+    fn cuboid(&mut self, transform: &Mat4f, extent: Vec3f) {
+        let inv = match inverse(transform) {
+            Some(inv) => inv,
+            None => return,
+        };
+        let corner = |x: usize, y: usize, z: usize| {
+            let local = Vec3f::new(
+                x as f32 * extent.x,
+                y as f32 * extent.y,
+                z as f32 * extent.z,
+            );
+            transform_point(transform, local)
+        };
+        for axis in 0..3 {
+            for side in 0..2 {
+                // Corners of the face in order around it
+                let quad: Vec<Vec3f> = [(0, 0), (1, 0), (1, 1), (0, 1)]
+                    .iter()
+                    .map(|&(u, v)| {
+                        let mut c = [0; 3];
+                        c[axis] = side;
+                        c[(axis + 1) % 3] = u;
+                        c[(axis + 2) % 3] = v;
+                        corner(c[0], c[1], c[2])
+                    })
+                    .collect();
+                let normal = Vec3f::ith(axis, if side == 0 { -1. } else { 1. });
+                let normal = (inv.fixed_view::<3, 3>(0, 0).transpose() * normal).normalize();
+                self.push_flat([quad[0], quad[1], quad[2]], normal);
+                self.push_flat([quad[0], quad[2], quad[3]], normal);
+            }
+        }
+    }
+}
+
+/// Name of the material of the object in the exported files
+fn material_name(obj: &Object) -> String {
+    match obj.material {
+        MaterialRef::Named(ref name) => name.clone(),
+        MaterialRef::Inline(_) => format!("{}.mat", obj.name),
+    }
+}
+
+/// Every object with shapes as triangles, and the materials they use by name
+fn tessellate(scene: &Scene) -> (Vec<Mesh>, BTreeMap<String, Material>) {
+    let mut meshes = Vec::new();
+    let mut materials = BTreeMap::new();
+    for obj in &scene.objects {
+        if obj.shapes.is_empty() {
+            continue;
+        }
+        let mut mesh = Mesh {
+            name: obj.name.clone(),
+            material: material_name(obj),
+            vertices: Vec::new(),
+            colored: false,
+        };
+        for shape in &obj.shapes {
+            match *shape {
+                Geometry::Sphere(s) => {
+                    mesh.sphere(translation(&obj.world), s.scaled_radius(&obj.world))
+                }
+                Geometry::Cuboid(c) => mesh.cuboid(&obj.world, c.extent),
+                Geometry::Triangle(t) => {
+                    let (a, b, c) = t.corners(&obj.world);
+                    let normal = (b - a).cross(&(c - a)).normalize();
+                    let colors = match t.colors {
+                        Some((ca, cb, cc)) => [ca, cb, cc],
+                        None => [Vec3f::repeat(1.); 3],
+                    };
+                    mesh.colored |= t.colors.is_some();
+                    mesh.push([a, b, c], [normal; 3], colors);
+                }
+            }
+        }
+        materials.insert(mesh.material.clone(), obj.mat);
+        meshes.push(mesh);
+    }
+    (meshes, materials)
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<(), String> {
+    std::fs::write(path, data).map_err(|e| format!("Can't write {}: {}", path.display(), e))
+}
+
+/// Writes the scene as OBJ with the materials in an MTL file next to it
+pub fn write_obj(scene: &Scene, path: &Path) -> Result<(), String> {
+    let (meshes, materials) = tessellate(scene);
+    let mtl_path = path.with_extension("mtl");
+    let mtl_name = mtl_path
+        .file_name()
+        .map_or(String::new(), |n| n.to_string_lossy().into_owned());
+
+    let mut obj = String::new();
+    writeln!(obj, "mtllib {}", mtl_name).unwrap();
+    let mut count = 0;
+    for mesh in &meshes {
+        writeln!(obj, "o {}", mesh.name).unwrap();
+        for v in &mesh.vertices {
+            let p = v.pos;
+            if mesh.colored {
+                // Vertex colors after the position are understood by most tools
+                let c = v.color;
+                writeln!(obj, "v {} {} {} {} {} {}", p.x, p.y, p.z, c.x, c.y, c.z).unwrap();
+            } else {
+                writeln!(obj, "v {} {} {}", p.x, p.y, p.z).unwrap();
+            }
+        }
+        for v in &mesh.vertices {
+            writeln!(obj, "vn {} {} {}", v.normal.x, v.normal.y, v.normal.z).unwrap();
+        }
+        writeln!(obj, "usemtl {}", mesh.material).unwrap();
+        for i in (0..mesh.vertices.len()).step_by(3) {
+            let (a, b, c) = (count + i + 1, count + i + 2, count + i + 3);
+            writeln!(obj, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c).unwrap();
+        }
+        count += mesh.vertices.len();
+    }
+
+    let mut mtl = String::new();
+    for (name, m) in &materials {
+        writeln!(mtl, "newmtl {}", name).unwrap();
+        writeln!(mtl, "Ka {} {} {}", m.ambient.x, m.ambient.y, m.ambient.z).unwrap();
+        writeln!(mtl, "Kd {} {} {}", m.diffuse.x, m.diffuse.y, m.diffuse.z).unwrap();
+        writeln!(mtl, "Ks {} {} {}", m.specular.x, m.specular.y, m.specular.z).unwrap();
+        writeln!(mtl, "Ns {}", m.shininess).unwrap();
+        // Illumination models 3 and 7 turn on ray traced reflection and refraction
+        if m.refraction > 0. {
+            writeln!(mtl, "Ni {}", m.refraction).unwrap();
+            writeln!(mtl, "illum 7").unwrap();
+        } else if m.reflection > 0. {
+            writeln!(mtl, "illum 3").unwrap();
+        } else {
+            writeln!(mtl, "illum 2").unwrap();
+        }
+        writeln!(mtl).unwrap();
+    }
+
+    write_file(path, obj.as_bytes())?;
+    write_file(&mtl_path, mtl.as_bytes())
+}
+
+/// Data of the glTF buffer with the views and accessors pointing into it
+struct Builder {
+    root: json::Root,
+    data: Vec<u8>,
+}
+
+impl Builder {
+    /// Adds a view on the vectors and an accessor reading them
+    fn vec3s(&mut self, values: &[Vec3f], bounds: bool) -> json::Index<json::Accessor> {
+        let offset = self.data.len();
+        for v in values {
+            for c in v.iter() {
+                self.data.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        let view = self.root.push(json::buffer::View {
+            buffer: json::Index::new(0),
+            byte_length: USize64::from(self.data.len() - offset),
+            byte_offset: Some(USize64::from(offset)),
+            byte_stride: None,
+            extensions: Default::default(),
+            extras: Default::default(),
+            name: None,
+            target: Some(Valid(json::buffer::Target::ArrayBuffer)),
+        });
+        let (min, max) = if bounds {
+            let (min, max) = values.iter().fold(
+                (Vec3f::repeat(f32::MAX), Vec3f::repeat(f32::MIN)),
+                |(min, max), v| (min.inf(v), max.sup(v)),
+            );
+            (
+                Some(json::Value::from(min.as_slice())),
+                Some(json::Value::from(max.as_slice())),
+            )
+        } else {
+            (None, None)
+        };
+        self.root.push(json::Accessor {
+            buffer_view: Some(view),
+            byte_offset: None,
+            count: USize64::from(values.len()),
+            component_type: Valid(json::accessor::GenericComponentType(
+                json::accessor::ComponentType::F32,
+            )),
+            extensions: Default::default(),
+            extras: Default::default(),
+            type_: Valid(json::accessor::Type::Vec3),
+            min,
+            max,
+            name: None,
+            normalized: false,
+            sparse: None,
+        })
+    }
+
+    fn material(&mut self, name: &str, m: &Material) -> json::Index<json::Material> {
+        let mut mat = json::Material {
+            name: Some(name.to_string()),
+            pbr_metallic_roughness: json::material::PbrMetallicRoughness {
+                base_color_factor: json::material::PbrBaseColorFactor([
+                    m.diffuse.x,
+                    m.diffuse.y,
+                    m.diffuse.z,
+                    1.,
+                ]),
+                metallic_factor: json::material::StrengthFactor(m.reflection),
+                roughness_factor: json::material::StrengthFactor(shininess_to_roughness(
+                    m.shininess,
+                )),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        if m.refraction > 0. {
+            // Refracted light is what isn't reflected
+            mat.pbr_metallic_roughness.metallic_factor = json::material::StrengthFactor(0.);
+            mat.extensions = Some(json::extensions::material::Material {
+                transmission: Some(json::extensions::material::Transmission {
+                    transmission_factor: json::extensions::material::TransmissionFactor(
+                        1. - m.reflection,
+                    ),
+                    ..Default::default()
+                }),
+                ior: Some(json::extensions::material::Ior {
+                    ior: json::extensions::material::IndexOfRefraction(m.refraction),
+                    ..Default::default()
+                }),
+            });
+            for ext in &["KHR_materials_transmission", "KHR_materials_ior"] {
+                if !self.root.extensions_used.iter().any(|e| e == ext) {
+                    self.root.extensions_used.push(ext.to_string());
+                }
+            }
+        }
+        self.root.push(mat)
+    }
+
+    fn camera(&mut self, scene: &Scene) -> json::Index<json::Node> {
+        let cam = &scene.cam;
+        let aspect = cam.width as f32 / cam.height as f32;
+        let perspective = json::camera::Perspective {
+            aspect_ratio: Some(aspect),
+            yfov: 2. * cam.fov.to_radians(),
+            zfar: None,
+            znear: 0.01,
+            extensions: None,
+            extras: Default::default(),
+        };
+        let (perspective, orthographic) = match cam.projection {
+            Projection::Perspective => (Some(perspective), None),
+            Projection::Orthographic { height } => {
+                let ortho = json::camera::Orthographic {
+                    xmag: height / 2. * aspect,
+                    ymag: height / 2.,
+                    zfar: 1e4,
+                    znear: 0.,
+                    extensions: None,
+                    extras: Default::default(),
+                };
+                (None, Some(ortho))
+            }
+            _ => {
+                eprintln!("warning: camera projection can't be exported, written as perspective");
+                (Some(perspective), None)
+            }
+        };
+        let camera = self.root.push(json::Camera {
+            name: Some("camera".to_string()),
+            type_: Valid(if orthographic.is_some() {
+                json::camera::Type::Orthographic
+            } else {
+                json::camera::Type::Perspective
+            }),
+            perspective,
+            orthographic,
+            extensions: None,
+            extras: Default::default(),
+        });
+
+        // glTF cameras look along -z with y up
+        let back = -y_up(cam.dir).normalize();
+        let side = y_up(cam.up).cross(&back).normalize();
+        let up = back.cross(&side);
+        let mut m = Mat4f::identity();
+        m.fixed_view_mut::<3, 1>(0, 0).copy_from(&side);
+        m.fixed_view_mut::<3, 1>(0, 1).copy_from(&up);
+        m.fixed_view_mut::<3, 1>(0, 2).copy_from(&back);
+        m.fixed_view_mut::<3, 1>(0, 3).copy_from(&y_up(cam.eye));
+        let mut matrix = [0.; 16];
+        matrix.copy_from_slice(m.as_slice());
+        self.root.push(json::Node {
+            name: Some("camera".to_string()),
+            camera: Some(camera),
+            matrix: Some(matrix),
+            ..Default::default()
+        })
+    }
+
+    fn lights(&mut self, scene: &Scene) -> Vec<json::Index<json::Node>> {
+        let mut nodes = Vec::new();
+        let mut lights = Vec::new();
+        for (i, light) in scene.lights.iter().enumerate() {
+            // Colors brighter than white go into the intensity
+            let intensity = light.color.max().max(1.);
+            lights.push(json::extensions::scene::khr_lights_punctual::Light {
+                color: (light.color / intensity).into(),
+                extensions: None,
+                extras: Default::default(),
+                intensity,
+                name: Some(format!("light{}", i)),
+                range: None,
+                spot: None,
+                type_: Valid(json::extensions::scene::khr_lights_punctual::Type::Point),
+            });
+            let pos = y_up(light.pos);
+            nodes.push(self.root.push(json::Node {
+                name: Some(format!("light{}", i)),
+                translation: Some([pos.x, pos.y, pos.z]),
+                extensions: Some(json::extensions::scene::Node {
+                    khr_lights_punctual: Some(
+                        json::extensions::scene::khr_lights_punctual::KhrLightsPunctual {
+                            light: json::Index::new(i as u32),
+                        },
+                    ),
+                }),
+                ..Default::default()
+            }));
+        }
+        if !lights.is_empty() {
+            self.root.extensions = Some(json::extensions::Root {
+                khr_lights_punctual: Some(json::extensions::root::KhrLightsPunctual { lights }),
+            });
+            self.root
+                .extensions_used
+                .push("KHR_lights_punctual".to_string());
+        }
+        nodes
+    }
+}
+
+/// Writes the scene as glTF, into a single file when `binary` is set
+/// and as a `.gltf` file with a `.bin` buffer next to it otherwise
+pub fn write_gltf(scene: &Scene, path: &Path, binary: bool) -> Result<(), String> {
+    let (meshes, materials) = tessellate(scene);
+    let mut b = Builder {
+        root: json::Root::default(),
+        data: Vec::new(),
+    };
+    let material_indices: BTreeMap<&String, json::Index<json::Material>> = materials
+        .iter()
+        .map(|(name, m)| (name, b.material(name, m)))
+        .collect();
+
+    let mut nodes = Vec::new();
+    for mesh in &meshes {
+        let positions: Vec<Vec3f> = mesh.vertices.iter().map(|v| v.pos).collect();
+        let normals: Vec<Vec3f> = mesh.vertices.iter().map(|v| v.normal).collect();
+        let mut attributes = BTreeMap::new();
+        attributes.insert(
+            Valid(json::mesh::Semantic::Positions),
+            b.vec3s(&positions, true),
+        );
+        attributes.insert(
+            Valid(json::mesh::Semantic::Normals),
+            b.vec3s(&normals, false),
+        );
+        if mesh.colored {
+            let colors: Vec<Vec3f> = mesh.vertices.iter().map(|v| v.color).collect();
+            attributes.insert(
+                Valid(json::mesh::Semantic::Colors(0)),
+                b.vec3s(&colors, false),
+            );
+        }
+        let primitive = json::mesh::Primitive {
+            attributes,
+            extensions: Default::default(),
+            extras: Default::default(),
+            indices: None,
+            material: Some(material_indices[&mesh.material]),
+            mode: Valid(json::mesh::Mode::Triangles),
+            targets: None,
+        };
+        let index = b.root.push(json::Mesh {
+            extensions: Default::default(),
+            extras: Default::default(),
+            name: Some(mesh.name.clone()),
+            primitives: vec![primitive],
+            weights: None,
+        });
+        nodes.push(b.root.push(json::Node {
+            name: Some(mesh.name.clone()),
+            mesh: Some(index),
+            ..Default::default()
+        }));
+    }
+    nodes.push(b.camera(scene));
+    nodes.extend(b.lights(scene));
+    let scene_index = b.root.push(json::Scene {
+        extensions: Default::default(),
+        extras: Default::default(),
+        name: None,
+        nodes,
+    });
+    b.root.scene = Some(scene_index);
+
+    let bin_path = path.with_extension("bin");
+    b.root.push(json::Buffer {
+        byte_length: USize64::from(b.data.len()),
+        extensions: Default::default(),
+        extras: Default::default(),
+        name: None,
+        uri: if binary {
+            None
+        } else {
+            bin_path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+        },
+    });
+    b.root.asset.generator = Some("rt".to_string());
+
+    if binary {
+        let text = json::serialize::to_string(&b.root).map_err(|e| e.to_string())?;
+        let glb = gltf::binary::Glb {
+            header: gltf::binary::Header {
+                magic: *b"glTF",
+                version: 2,
+                // Filled in on writing
+                length: 0,
+            },
+            bin: Some(Cow::Owned(b.data)),
+            json: Cow::Owned(text.into_bytes()),
+        };
+        let data = glb.to_vec().map_err(|e| e.to_string())?;
+        write_file(path, &data)
+    } else {
+        let text = json::serialize::to_string_pretty(&b.root).map_err(|e| e.to_string())?;
+        write_file(path, text.as_bytes())?;
+        write_file(&bin_path, &b.data)
+    }
+}
+
+/// Loads the scene, puts it where it is `time` seconds into its animation
+/// and writes it in the format matching the extension of `out`
+pub fn export(file: &str, out: &str, time: f32) -> Result<(), String> {
+    let mut scene = Scene::load(Path::new(file)).map_err(|e| e.to_string())?;
+    for w in &scene.warnings {
+        eprintln!("{}", w);
+    }
+    scene.evaluate_at(time);
+
+    let path = Path::new(out);
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match ext.as_str() {
+        "obj" => write_obj(&scene, path)?,
+        "gltf" => write_gltf(&scene, path, false)?,
+        "glb" => write_gltf(&scene, path, true)?,
+        _ => {
+            return Err(format!(
+                "{}: unknown format, expected .obj, .gltf or .glb",
+                out
+            ))
+        }
+    }
+    println!("{}: written to {}", file, out);
+    Ok(())
+}
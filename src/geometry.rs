@@ -1,6 +1,6 @@
 use crate::raytracer::Ray;
 
-use crate::math::{Vec3f, Vec3, Mat4f, translation};
+use crate::math::{Vec3f, Vec3, Mat4f, inverse, scale, translation, transform_point};
 
 use std::f32::INFINITY;
 
@@ -11,6 +11,11 @@ use serde::{Serialize, Deserialize};
 pub trait Shape : Send + Sync {
     fn get_normal(&self, transform: &Mat4f, ray: &Ray, tnear: f32) -> Vec3f;
     fn intersect(&self, transform: &Mat4f, ray: &Ray) -> (f32, f32);
+
+    /// Color of the surface at `point`, for shapes with vertex colors
+    fn color_at(&self, _transform: &Mat4f, _point: Vec3f) -> Option<Vec3f> {
+        None
+    }
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
@@ -18,6 +23,8 @@ pub struct Sphere {
     pub radius: f32,
 }
 
+/// Spans from the origin of its object to `extent`, the object transform
+/// places, turns and stretches it
 #[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct Cuboid {
     pub extent: Vec3f,
@@ -28,6 +35,10 @@ pub struct Triangle {
     pub a: Vec3f,
     pub b: Vec3f,
     pub c: Vec3f,
+
+    /// Colors of `a`, `b` and `c`, they tint the material diffuse color
+    #[serde(default)]
+    pub colors: Option<(Vec3f, Vec3f, Vec3f)>,
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
@@ -56,13 +67,24 @@ impl Shape for Geometry {
             Sphere(sphere) => sphere.intersect(transform, ray),
         }
     }
+
+    fn color_at(&self, transform: &Mat4f, point: Vec3f) -> Option<Vec3f> {
+        match *self {
+            Geometry::Triangle(tri) => tri.color_at(transform, point),
+            _ => None,
+        }
+    }
 }
 
 impl Shape for Cuboid {
     fn get_normal(&self, transform: &Mat4f, ray: &Ray, tnear: f32) -> Vec3f {
-        let vmin = translation(transform);
-        let vmax = vmin + self.extent;
-        let phit = ray.origin + ray.dir * tnear;
+        let inv = match inverse(transform) {
+            Some(inv) => inv,
+            None => return Vec3::zero(),
+        };
+        let vmin = Vec3f::zeros();
+        let vmax = self.extent;
+        let phit = transform_point(&inv, ray.origin + ray.dir * tnear);
 
         let phit_min = phit - vmin;
         let phit_max = phit - vmax;
@@ -86,13 +108,21 @@ impl Shape for Cuboid {
         if phit_max.z.abs() < eps {
             nhit.z = 1.
         }
-        nhit
+        // Normals go back to world space through the inverse transpose
+        let nworld = inv.fixed_view::<3, 3>(0, 0).transpose() * nhit;
+        nworld.try_normalize(0.).unwrap_or(nhit)
     }
 
     fn intersect(&self, transform: &Mat4f, ray: &Ray) -> (f32, f32) {
         let (mut t0, t1) = (INFINITY, INFINITY);
-        let o = ray.origin;
-        let mut d = ray.dir;
+        // Slabs are tested in the box's own space, distances along the ray
+        // stay the same as the direction isn't normalized there
+        let inv = match inverse(transform) {
+            Some(inv) => inv,
+            None => return (t0, t1),
+        };
+        let o = transform_point(&inv, ray.origin);
+        let mut d = inv.transform_vector(&ray.dir);
 
         d.x = 1. / d.x;
         d.y = 1. / d.y;
@@ -103,8 +133,8 @@ impl Shape for Cuboid {
         sign.y = d.y > 0.;
         sign.z = d.z > 0.;
 
-        let b0 = translation(transform);
-        let b1 = b0 + self.extent;
+        let b0 = Vec3f::zeros();
+        let b1 = self.extent;
 
         let mut tmin = (if sign.x { b0.x } else { b1.x } - o.x) * d.x;
         let mut tmax = (if sign.x { b1.x } else { b0.x } - o.x) * d.x;
@@ -147,6 +177,14 @@ impl Shape for Cuboid {
     }
 }
 
+impl Sphere {
+    /// Radius after the object transform, spheres stay round and take the
+    /// largest of its scale factors
+    pub fn scaled_radius(&self, transform: &Mat4f) -> f32 {
+        self.radius * scale(transform).max()
+    }
+}
+
 impl Shape for Sphere {
     fn get_normal(&self, transform: &Mat4f, ray: &Ray, tnear: f32) -> Vec3f {
         let center = translation(transform);
@@ -166,7 +204,8 @@ impl Shape for Sphere {
             return (t0, t1);
         }
         let d2 = l.dot(&l) - tca * tca;
-        let r2 = self.radius * self.radius;
+        let radius = self.scaled_radius(transform);
+        let r2 = radius * radius;
         if d2 > r2 {
             return (t0, t1);
         }
@@ -177,13 +216,23 @@ impl Shape for Sphere {
     }
 }
 
+impl Triangle {
+    /// Vertices placed by the object transform
+    pub fn corners(&self, transform: &Mat4f) -> (Vec3f, Vec3f, Vec3f) {
+        (
+            transform_point(transform, self.a),
+            transform_point(transform, self.b),
+            transform_point(transform, self.c),
+        )
+    }
+}
+
 impl Shape for Triangle {
-    fn get_normal(&self, transform: &Mat4f, ray: &Ray, tnear: f32) -> Vec3f {
-        let t = ray.origin + ray.dir * tnear;
-        let origin = translation(transform);
-        let edge_0 = t - self.a + origin;
-        let edge_1 = t - self.b + origin;
-        let mut nhit = edge_1.cross(&edge_0).normalize();
+    fn get_normal(&self, transform: &Mat4f, ray: &Ray, _tnear: f32) -> Vec3f {
+        let (a, b, c) = self.corners(transform);
+        let edge_0 = b - a;
+        let edge_1 = c - a;
+        let mut nhit = edge_0.cross(&edge_1).normalize();
         if ray.dir.dot(&nhit) > 0. {
             nhit = -nhit;
         }
@@ -192,9 +241,9 @@ impl Shape for Triangle {
 
     fn intersect(&self, transform: &Mat4f, ray: &Ray) -> (f32, f32) {
         let (t0, t1) = (INFINITY, INFINITY);
-        let origin = translation(transform);
-        let edge_0 = self.b - self.a + origin;
-        let edge_1 = self.c - self.a + origin;
+        let (a, b, c) = self.corners(transform);
+        let edge_0 = b - a;
+        let edge_1 = c - a;
         let h = ray.dir.cross(&edge_1);
 
         let dot = edge_0.dot(&h);
@@ -203,7 +252,7 @@ impl Shape for Triangle {
         }
 
         let dot_r = 1. / dot;
-        let s = ray.origin - self.a + origin;
+        let s = ray.origin - a;
         let u = dot_r * s.dot(&h);
         if u < 0. || u > 1. {
             return (t0, t1);
@@ -222,4 +271,92 @@ impl Shape for Triangle {
             (t0, t1)
         }
     }
+
+    fn color_at(&self, transform: &Mat4f, point: Vec3f) -> Option<Vec3f> {
+        let (ca, cb, cc) = self.colors?;
+        let (a, b, c) = self.corners(transform);
+        // Barycentric weights from the areas of the sub-triangles
+        let area = (b - a).cross(&(c - a)).norm();
+        let wa = (b - point).cross(&(c - point)).norm() / area;
+        let wb = (c - point).cross(&(a - point)).norm() / area;
+        let wc = (1. - wa - wb).max(0.);
+        Some(ca * wa + cb * wb + cc * wc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{set_rotation, set_scale, set_translation};
+
+    fn ray(origin: Vec3f, dir: Vec3f) -> Ray {
+        Ray {
+            origin,
+            dir,
+            time: 0.,
+        }
+    }
+
+    fn close(a: Vec3f, b: Vec3f) -> bool {
+        (a - b).norm() < 1e-4
+    }
+
+    #[test]
+    fn turned_box() {
+        let cuboid = Cuboid {
+            extent: Vec3f::new(2., 1., 1.),
+        };
+        // The long side ends up along y, the box covers x from -1 to 0
+        let mut transform = Mat4f::identity();
+        set_rotation(&mut transform, Vec3f::new(0., 0., 90.));
+
+        let down = ray(Vec3f::new(-0.5, 1.5, 10.), Vec3f::new(0., 0., -1.));
+        let (t, _) = cuboid.intersect(&transform, &down);
+        assert!((t - 9.).abs() < 1e-4);
+        assert!(close(
+            cuboid.get_normal(&transform, &down, t),
+            Vec3f::new(0., 0., 1.)
+        ));
+
+        let side = ray(Vec3f::new(-0.5, 10., 0.5), Vec3f::new(0., -1., 0.));
+        let (t, _) = cuboid.intersect(&transform, &side);
+        assert!((t - 8.).abs() < 1e-4);
+        assert!(close(
+            cuboid.get_normal(&transform, &side, t),
+            Vec3f::new(0., 1., 0.)
+        ));
+
+        let past = ray(Vec3f::new(0.5, 0.5, 10.), Vec3f::new(0., 0., -1.));
+        assert!(cuboid.intersect(&transform, &past).0.is_infinite());
+    }
+
+    #[test]
+    fn stretched_box() {
+        let cuboid = Cuboid {
+            extent: Vec3f::new(1., 1., 1.),
+        };
+        let mut transform = Mat4f::identity();
+        set_scale(&mut transform, Vec3f::new(1., 1., 4.));
+        set_translation(&mut transform, Vec3f::new(0., 0., -2.));
+
+        let down = ray(Vec3f::new(0.5, 0.5, 10.), Vec3f::new(0., 0., -1.));
+        let (t, _) = cuboid.intersect(&transform, &down);
+        assert!((t - 8.).abs() < 1e-4);
+        assert!(close(
+            cuboid.get_normal(&transform, &down, t),
+            Vec3f::new(0., 0., 1.)
+        ));
+    }
+
+    #[test]
+    fn scaled_sphere() {
+        let sphere = Sphere { radius: 1. };
+        let mut transform = Mat4f::identity();
+        set_scale(&mut transform, Vec3f::new(1., 3., 1.));
+        assert_eq!(sphere.scaled_radius(&transform), 3.);
+
+        let down = ray(Vec3f::new(0., 0., 10.), Vec3f::new(0., 0., -1.));
+        let (t0, t1) = sphere.intersect(&transform, &down);
+        assert!((t0 - 7.).abs() < 1e-4 && (t1 - 13.).abs() < 1e-4);
+    }
 }
@@ -0,0 +1,548 @@
+//! glTF 2.0 import. Meshes become triangle objects keeping the node
+//! hierarchy, PBR materials are approximated with Phong ones, the first
+//! camera and punctual lights are taken over and node animations become
+//! keyframe tracks. Everything else is dropped with a warning.
+
+use crate::camera::{CamBuilder, Camera, Projection};
+use crate::geometry::{Geometry, Triangle};
+use crate::keyframe::{Easing, Interpolation, Keyframe, Property, Repeat, Track};
+use crate::light::Light;
+use crate::material::{roughness_to_shininess, Material, MaterialLibrary, MaterialRef};
+use crate::math::{transform_point, translation, Mat4f, Vec3f};
+use crate::na::{Quaternion, UnitQuaternion};
+use crate::object::Object;
+use crate::scene::{Scene, SceneError};
+use crate::validation::{Issue, Severity};
+
+use gltf::animation::util::ReadOutputs;
+use gltf::khr_lights_punctual::Kind;
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// glTF is right handed with y pointing up, the renderer world is left handed
+/// with y pointing down, flipping y alone turns one into the other
+fn y_down() -> Mat4f {
+    Mat4f::new(
+        1., 0., 0., 0., //
+        0., -1., 0., 0., //
+        0., 0., 1., 0., //
+        0., 0., 0., 1.,
+    )
+}
+
+/// Image height of imported cameras, the width follows their aspect ratio
+const IMAGE_HEIGHT: u32 = 128;
+
+/// Directional lights are placed this far away against their direction
+const SUN_DISTANCE: f32 = 1000.;
+
+fn vec3(v: [f32; 3]) -> Vec3f {
+    Vec3f::new(v[0], v[1], v[2])
+}
+
+/// Object which only groups its children
+fn group(name: &str, transform: Mat4f) -> Object {
+    Object::new(
+        name,
+        Vec::new(),
+        MaterialRef::Inline(Material::default()),
+        transform,
+    )
+}
+
+struct Importer<'a> {
+    buffers: &'a [gltf::buffer::Data],
+    /// Object names by node index
+    names: HashMap<usize, String>,
+    used_names: HashSet<String>,
+    materials: MaterialLibrary,
+    lights: Vec<Light>,
+    camera: Option<Camera>,
+    /// World space vertices, used to frame the scene when there is no camera
+    points: Vec<Vec3f>,
+    warnings: Vec<Issue>,
+}
+
+impl<'a> Importer<'a> {
+    fn warn(&mut self, item: String, field: &str, message: &str) {
+        self.warnings.push(Issue {
+            severity: Severity::Warning,
+            item,
+            field: field.to_string(),
+            message: message.to_string(),
+            line: None,
+        });
+    }
+
+    fn unique_name(&mut self, name: String) -> String {
+        let mut unique = name.clone();
+        let mut i = 1;
+        while self.used_names.contains(&unique) {
+            unique = format!("{}.{}", name, i);
+            i += 1;
+        }
+        self.used_names.insert(unique.clone());
+        unique
+    }
+
+    /// Name of the material in the scene library, converting it on first use
+    fn material(&mut self, m: &gltf::Material) -> String {
+        let name = match (m.index(), m.name()) {
+            (None, _) => "default".to_string(),
+            (Some(_), Some(name)) => name.to_string(),
+            (Some(i), None) => format!("material{}", i),
+        };
+        if self.materials.contains_key(&name) {
+            return name;
+        }
+        let item = format!("material \"{}\"", name);
+
+        let pbr = m.pbr_metallic_roughness();
+        let [r, g, b, alpha] = pbr.base_color_factor();
+        let base = Vec3f::new(r, g, b);
+        let metallic = pbr.metallic_factor();
+        let roughness = pbr.roughness_factor();
+        let dielectric = Vec3f::repeat(0.04);
+        let mut mat = Material {
+            ambient: Vec3f::repeat(0.1),
+            diffuse: base * (1. - metallic),
+            specular: dielectric + (base - dielectric) * metallic,
+            shininess: roughness_to_shininess(roughness),
+            reflection: metallic * (1. - roughness),
+            refraction: 0.,
+        };
+        if let Some(t) = m.transmission() {
+            if t.transmission_factor() > 0. {
+                mat.refraction = m.ior().unwrap_or(1.5);
+                // Refracted light only shows through reflective materials
+                mat.reflection = mat.reflection.max(1. - t.transmission_factor()).max(0.04);
+            }
+        }
+
+        let textures = [
+            ("baseColorTexture", pbr.base_color_texture().is_some()),
+            (
+                "metallicRoughnessTexture",
+                pbr.metallic_roughness_texture().is_some(),
+            ),
+            ("normalTexture", m.normal_texture().is_some()),
+            ("occlusionTexture", m.occlusion_texture().is_some()),
+            ("emissiveTexture", m.emissive_texture().is_some()),
+        ];
+        for (field, present) in textures.iter() {
+            if *present {
+                self.warn(item.clone(), field, "textures are not supported, ignored");
+            }
+        }
+        if m.emissive_factor().iter().any(|c| *c > 0.) {
+            self.warn(
+                item.clone(),
+                "emissiveFactor",
+                "emission is not supported, ignored",
+            );
+        }
+        if alpha < 1. || m.alpha_mode() != gltf::material::AlphaMode::Opaque {
+            self.warn(
+                item,
+                "alphaMode",
+                "transparency is not supported, rendered opaque",
+            );
+        }
+
+        self.materials.insert(name.clone(), mat);
+        name
+    }
+
+    /// Triangles of a primitive in mesh space, `None` for other primitive kinds
+    fn triangles(
+        &mut self,
+        p: &gltf::Primitive,
+        world: &Mat4f,
+        item: &str,
+    ) -> Option<Vec<Geometry>> {
+        if p.mode() != gltf::mesh::Mode::Triangles {
+            let message = format!("{:?} primitives are not supported, skipped", p.mode());
+            self.warn(item.to_string(), "mode", &message);
+            return None;
+        }
+        let buffers = self.buffers;
+        let reader = p.reader(|b| Some(&buffers[b.index()].0[..]));
+        let positions: Vec<Vec3f> = match reader.read_positions() {
+            Some(iter) => iter.map(vec3).collect(),
+            None => return None,
+        };
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+
+        let mut shapes = Vec::new();
+        let mut degenerate = 0;
+        for tri in indices.chunks_exact(3) {
+            let corner = |i: u32| positions.get(i as usize).cloned();
+            let (a, b, c) = match (corner(tri[0]), corner(tri[1]), corner(tri[2])) {
+                (Some(a), Some(b), Some(c)) => (a, b, c),
+                _ => continue,
+            };
+            if (b - a).cross(&(c - a)).norm() < 1e-8 {
+                degenerate += 1;
+                continue;
+            }
+            shapes.push(Geometry::Triangle(Triangle {
+                a,
+                b,
+                c,
+                colors: None,
+            }));
+        }
+        if degenerate > 0 {
+            let message = format!("{} degenerate triangles skipped", degenerate);
+            self.warn(item.to_string(), "indices", &message);
+        }
+        self.points
+            .extend(positions.iter().map(|p| transform_point(world, *p)));
+        Some(shapes)
+    }
+
+    fn camera(&mut self, cam: &gltf::Camera, world: &Mat4f) {
+        let eye = translation(world);
+        let dir = (world.fixed_view::<3, 3>(0, 0) * Vec3f::new(0., 0., -1.)).normalize();
+        let up = (world.fixed_view::<3, 3>(0, 0) * Vec3f::new(0., 1., 0.)).normalize();
+        let (projection, fov, aspect) = match cam.projection() {
+            gltf::camera::Projection::Perspective(p) => {
+                // Renderer fov is half of the vertical angle
+                (
+                    Projection::Perspective,
+                    p.yfov().to_degrees() / 2.,
+                    p.aspect_ratio(),
+                )
+            }
+            gltf::camera::Projection::Orthographic(o) => {
+                let height = 2. * o.ymag();
+                (
+                    Projection::Orthographic { height },
+                    30.,
+                    Some(o.xmag() / o.ymag()),
+                )
+            }
+        };
+        let width = (IMAGE_HEIGHT as f32 * aspect.unwrap_or(1.)).round() as u32;
+        let mut camera = CamBuilder::new()
+            .eye(eye)
+            .center(eye + dir)
+            .up(up)
+            .fov(fov)
+            .width(width.max(1))
+            .height(IMAGE_HEIGHT)
+            .projection(projection)
+            .build();
+        // Keeps the viewer's mouse look consistent with the imported direction
+        camera.look_at(eye + dir);
+        self.camera = Some(camera);
+    }
+
+    fn light(&mut self, light: &gltf::khr_lights_punctual::Light, world: &Mat4f) {
+        let item = format!("light \"{}\"", light.name().unwrap_or("unnamed"));
+        let mut pos = translation(world);
+        match light.kind() {
+            Kind::Point => {}
+            Kind::Spot { .. } => {
+                self.warn(item.clone(), "type", "spot light imported as a point light")
+            }
+            Kind::Directional => {
+                let dir = (world.fixed_view::<3, 3>(0, 0) * Vec3f::new(0., 0., -1.)).normalize();
+                pos -= dir * SUN_DISTANCE;
+                self.warn(
+                    item.clone(),
+                    "type",
+                    "directional light imported as a distant point light",
+                );
+            }
+        }
+        if light.intensity() != 1. || light.range().is_some() {
+            self.warn(
+                item,
+                "intensity",
+                "intensity and range are not supported, only the color is used",
+            );
+        }
+        self.lights.push(Light {
+            pos,
+            color: vec3(light.color()),
+            motion: None,
+        });
+    }
+
+    /// Object for the node, `None` when there is nothing to render in its subtree
+    fn node(&mut self, node: gltf::Node, parent_world: &Mat4f) -> Option<Object> {
+        let local = Mat4f::from(node.transform().matrix());
+        let world = parent_world * local;
+        let base_name = node
+            .name()
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("node{}", node.index()));
+        let item = format!("node \"{}\"", base_name);
+
+        if let Some(cam) = node.camera() {
+            if self.camera.is_none() {
+                self.camera(&cam, &world);
+            } else {
+                self.warn(item.clone(), "camera", "only the first camera is used");
+            }
+        }
+        if let Some(light) = node.light() {
+            self.light(&light, &world);
+        }
+        if node.skin().is_some() {
+            self.warn(
+                item.clone(),
+                "skin",
+                "skinning is not supported, rendered in bind pose",
+            );
+        }
+
+        let mut parts = Vec::new();
+        if let Some(mesh) = node.mesh() {
+            if mesh.weights().is_some() || node.weights().is_some() {
+                self.warn(
+                    item.clone(),
+                    "weights",
+                    "morph targets are not supported, ignored",
+                );
+            }
+            for p in mesh.primitives() {
+                if let Some(shapes) = self.triangles(&p, &world, &item) {
+                    let mat = self.material(&p.material());
+                    parts.push((shapes, mat));
+                }
+            }
+        }
+        let children: Vec<Object> = node
+            .children()
+            .filter_map(|c| self.node(c, &world))
+            .collect();
+        if parts.is_empty() && children.is_empty() {
+            return None;
+        }
+
+        let name = self.unique_name(base_name);
+        self.names.insert(node.index(), name.clone());
+        // Primitives with different materials become children of the node object
+        let mut obj = if parts.len() == 1 {
+            let (shapes, mat) = parts.remove(0);
+            Object::new(&name, shapes, MaterialRef::Named(mat), local)
+        } else {
+            group(&name, local)
+        };
+        for (i, (shapes, mat)) in parts.into_iter().enumerate() {
+            let part = self.unique_name(format!("{}/{}", name, i));
+            obj.children.push(Object::new(
+                &part,
+                shapes,
+                MaterialRef::Named(mat),
+                Mat4f::identity(),
+            ));
+        }
+        obj.children.extend(children);
+        Some(obj)
+    }
+
+    /// Camera looking at all imported vertices along the z axis
+    fn framing_camera(&self) -> Camera {
+        let (min, max) = self.points.iter().fold(
+            (Vec3f::repeat(f32::MAX), Vec3f::repeat(f32::MIN)),
+            |(min, max), p| (min.inf(p), max.sup(p)),
+        );
+        let (center, radius) = if self.points.is_empty() {
+            (Vec3f::zeros(), 1.)
+        } else {
+            ((min + max) / 2., ((max - min) / 2.).norm().max(1e-3))
+        };
+        // Fits the bounding sphere into the 30 degree half angle
+        let eye = center + Vec3f::new(0., 0., 2. * radius);
+        let mut camera = CamBuilder::new()
+            .eye(eye)
+            .center(center)
+            .up(Vec3f::new(0., -1., 0.))
+            .fov(30.)
+            .width(IMAGE_HEIGHT)
+            .height(IMAGE_HEIGHT)
+            .build();
+        camera.look_at(center);
+        camera
+    }
+
+    fn animations(&mut self, doc: &gltf::Document) -> Vec<Track> {
+        let mut tracks = Vec::new();
+        for anim in doc.animations() {
+            let item = format!("animation \"{}\"", anim.name().unwrap_or("unnamed"));
+            for channel in anim.channels() {
+                let target = channel.target();
+                let name = match self.names.get(&target.node().index()) {
+                    Some(name) => name.clone(),
+                    None => {
+                        self.warn(
+                            item.clone(),
+                            "channels",
+                            "targets a node without geometry, skipped",
+                        );
+                        continue;
+                    }
+                };
+                let buffers = self.buffers;
+                let reader = channel.reader(|b| Some(&buffers[b.index()].0[..]));
+                let times: Vec<f32> = match reader.read_inputs() {
+                    Some(inputs) => inputs.collect(),
+                    None => continue,
+                };
+                let (property, values): (Property, Vec<Vec3f>) = match reader.read_outputs() {
+                    Some(ReadOutputs::Translations(v)) => {
+                        (Property::Position(name), v.map(vec3).collect())
+                    }
+                    Some(ReadOutputs::Scales(v)) => (Property::Scale(name), v.map(vec3).collect()),
+                    Some(ReadOutputs::Rotations(v)) => {
+                        (Property::Rotation(name), euler_angles(v.into_f32()))
+                    }
+                    Some(ReadOutputs::MorphTargetWeights(_)) => {
+                        self.warn(
+                            item.clone(),
+                            "channels",
+                            "morph target weights are not supported, skipped",
+                        );
+                        continue;
+                    }
+                    None => continue,
+                };
+                tracks.push(track(
+                    property,
+                    channel.sampler().interpolation(),
+                    &times,
+                    &values,
+                ));
+            }
+        }
+        tracks
+    }
+}
+
+/// Euler angles in degrees. Every rotation has two sets of angles, the one
+/// closest to the previous key is taken so that interpolation doesn't spin around.
+fn euler_angles<I: Iterator<Item = [f32; 4]>>(rotations: I) -> Vec<Vec3f> {
+    let mut result: Vec<Vec3f> = Vec::new();
+    for [x, y, z, w] in rotations {
+        let (roll, pitch, yaw) =
+            UnitQuaternion::new_normalize(Quaternion::new(w, x, y, z)).euler_angles();
+        let angles = Vec3f::new(roll, pitch, yaw).map(f32::to_degrees);
+        let angles = match result.last() {
+            None => angles,
+            Some(prev) => {
+                let other = Vec3f::new(angles.x + 180., 180. - angles.y, angles.z + 180.);
+                let unwrap = |a: Vec3f| a.zip_map(prev, |a, p| a - ((a - p) / 360.).round() * 360.);
+                let (a, b) = (unwrap(angles), unwrap(other));
+                if (a - prev).norm() <= (b - prev).norm() {
+                    a
+                } else {
+                    b
+                }
+            }
+        };
+        result.push(angles);
+    }
+    result
+}
+
+/// Imported animations loop, as they do in most viewers
+fn track(
+    property: Property,
+    interpolation: gltf::animation::Interpolation,
+    times: &[f32],
+    values: &[Vec3f],
+) -> Track {
+    use gltf::animation::Interpolation as I;
+    let cubic = interpolation == I::CubicSpline && values.len() == 3 * times.len();
+    let keys = times
+        .iter()
+        .enumerate()
+        .map(|(i, &time)| {
+            if !cubic {
+                return Keyframe {
+                    time,
+                    value: values[i.min(values.len() - 1)],
+                    in_tangent: Vec3f::zeros(),
+                    out_tangent: Vec3f::zeros(),
+                };
+            }
+            // Hermite tangents turn into Bézier handles a third of the segment long
+            let before = if i > 0 { time - times[i - 1] } else { 0. };
+            let after = times.get(i + 1).map_or(0., |t| t - time);
+            Keyframe {
+                time,
+                value: values[3 * i + 1],
+                in_tangent: -values[3 * i] * before / 3.,
+                out_tangent: values[3 * i + 2] * after / 3.,
+            }
+        })
+        .collect();
+    let interpolation = match interpolation {
+        I::Step => Interpolation::Step,
+        I::Linear => Interpolation::Linear,
+        I::CubicSpline if cubic => Interpolation::Bezier,
+        I::CubicSpline => Interpolation::Linear,
+    };
+    Track {
+        property,
+        keys,
+        interpolation,
+        easing: Easing::Linear,
+        repeat: Repeat::Loop,
+    }
+}
+
+/// Reads a `.gltf` or `.glb` file into a scene with a single root object
+/// named after the file, warnings list what couldn't be imported
+pub fn import(path: &Path) -> Result<(Scene, Vec<Issue>), SceneError> {
+    let error = |e: gltf::Error| SceneError::Import {
+        file: path.to_path_buf(),
+        message: e.to_string(),
+    };
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(path).map_err(error)?;
+    let buffers = gltf::import_buffers(&document, path.parent(), blob).map_err(error)?;
+
+    let mut importer = Importer {
+        buffers: &buffers,
+        names: HashMap::new(),
+        used_names: HashSet::new(),
+        materials: MaterialLibrary::new(),
+        lights: Vec::new(),
+        camera: None,
+        points: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    let root_name = path
+        .file_stem()
+        .map_or("gltf".to_string(), |s| s.to_string_lossy().into_owned());
+    let root_name = importer.unique_name(root_name);
+    let mut root = group(&root_name, y_down());
+    if let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        for node in scene.nodes() {
+            if let Some(obj) = importer.node(node, &y_down()) {
+                root.children.push(obj);
+            }
+        }
+    }
+    let tracks = importer.animations(&document);
+
+    let cam = match importer.camera.take() {
+        Some(cam) => cam,
+        None => importer.framing_camera(),
+    };
+    let mut scene = Scene::new(cam, vec![root], importer.lights);
+    scene.materials = importer.materials;
+    scene.tracks = tracks;
+    Ok((scene, importer.warnings))
+}
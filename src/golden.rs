@@ -0,0 +1,121 @@
+//! Golden image tests: scenes are rendered small and compared with the
+//! references in `tests/golden`, `RT_BLESS=1 cargo test` rewrites them
+
+use crate::image_io::{read_ppm, write_ppm};
+use crate::renderer::{Renderer, RendererOptions};
+use crate::scene::Scene;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SIZE: u32 = 48;
+/// Channel difference still counted as equal, absorbs float rounding
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Share of pixels which may differ by more than `CHANNEL_TOLERANCE`
+const MAX_DIFFERENT: f32 = 0.002;
+
+fn root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn render(scene: &str, time: f32) -> Vec<u8> {
+    let mut scene = Scene::load(Path::new(scene)).unwrap_or_else(|e| panic!("{}", e));
+    scene.cam.width = SIZE;
+    scene.cam.height = SIZE;
+    scene.evaluate_at(time);
+    Renderer::new(&RendererOptions::default()).render(&scene)
+}
+
+/// Grey copy of the reference with the pixels over the tolerance in red
+fn diff_image(expected: &[u8], actual: &[u8]) -> (Vec<u8>, usize) {
+    let mut diff = Vec::with_capacity(expected.len());
+    let mut different = 0;
+    for (e, a) in expected.chunks(3).zip(actual.chunks(3)) {
+        let worst = e
+            .iter()
+            .zip(a)
+            .map(|(&e, &a)| (e as i16 - a as i16).unsigned_abs())
+            .max()
+            .unwrap_or(0);
+        if worst > u16::from(CHANNEL_TOLERANCE) {
+            different += 1;
+            diff.extend_from_slice(&[255, 0, 0]);
+        } else {
+            let grey = ((e[0] as u16 + e[1] as u16 + e[2] as u16) / 12) as u8;
+            diff.extend_from_slice(&[grey, grey, grey]);
+        }
+    }
+    (diff, different)
+}
+
+fn check(name: &str, scene: &str, time: f32) {
+    let actual = render(scene, time);
+    let reference = root().join("tests/golden").join(format!("{}.ppm", name));
+    if std::env::var_os("RT_BLESS").is_some() {
+        fs::create_dir_all(reference.parent().unwrap()).unwrap();
+        write_ppm(&reference, SIZE, SIZE, &actual).unwrap();
+        return;
+    }
+    let expected = read_ppm(&reference)
+        .unwrap_or_else(|e| panic!("{}, run with RT_BLESS=1 to create the reference", e));
+    assert_eq!(
+        (expected.width, expected.height),
+        (SIZE, SIZE),
+        "{} has the wrong size",
+        reference.display()
+    );
+
+    let (diff, different) = diff_image(&expected.pixels, &actual);
+    let share = different as f32 / (SIZE * SIZE) as f32;
+    if share > MAX_DIFFERENT {
+        let out: PathBuf = root().join("target/golden");
+        fs::create_dir_all(&out).unwrap();
+        let actual_path = out.join(format!("{}.actual.ppm", name));
+        let diff_path = out.join(format!("{}.diff.ppm", name));
+        write_ppm(&actual_path, SIZE, SIZE, &actual).unwrap();
+        write_ppm(&diff_path, SIZE, SIZE, &diff).unwrap();
+        panic!(
+            "{}: {} of {} pixels differ from {}, see {} and {}",
+            name,
+            different,
+            SIZE * SIZE,
+            reference.display(),
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[test]
+fn cornell() {
+    check("cornell", "~builtin:cornell", 0.);
+}
+
+#[test]
+fn material_ball() {
+    check("material_ball", "~builtin:material-ball", 0.);
+}
+
+#[test]
+fn shadows() {
+    check("shadows", "~builtin:shadows", 0.);
+}
+
+#[test]
+fn refraction() {
+    check("refraction", "~builtin:refraction", 0.);
+}
+
+#[test]
+fn many_lights() {
+    check("many_lights", "~builtin:many-lights", 0.);
+}
+
+#[test]
+fn default_scene() {
+    check(
+        "default_scene",
+        concat!(env!("CARGO_MANIFEST_DIR"), "/src/scene.ron"),
+        1.5,
+    );
+}
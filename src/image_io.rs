@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+/// 8-bit RGB image, rows top to bottom
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+pub fn write_ppm(path: &Path, width: u32, height: u32, pixels: &[u8]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(File::create(path)?);
+    write!(file, "P6\n{} {}\n255\n", width, height)?;
+    file.write_all(pixels)?;
+    file.flush()
+}
+
+/// Reads a binary (P6) PPM file with 8-bit channels
+pub fn read_ppm(path: &Path) -> io::Result<Image> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let invalid = |msg: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), msg),
+        )
+    };
+
+    // Header is four whitespace separated tokens, comments run to the end of the line
+    let mut tokens: Vec<String> = Vec::new();
+    let mut line = String::new();
+    while tokens.len() < 4 {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("truncated header"));
+        }
+        let content = line.split('#').next().unwrap_or("");
+        tokens.extend(content.split_whitespace().map(String::from));
+    }
+    if tokens[0] != "P6" {
+        return Err(invalid("only binary P6 images are supported"));
+    }
+    let parse = |s: &str| s.parse::<u32>().map_err(|_| invalid("malformed header"));
+    let (width, height, max) = (parse(&tokens[1])?, parse(&tokens[2])?, parse(&tokens[3])?);
+    if max != 255 {
+        return Err(invalid("only 8-bit images are supported"));
+    }
+
+    let mut pixels = vec![0; (width * height * 3) as usize];
+    reader.read_exact(&mut pixels)?;
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
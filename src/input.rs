@@ -14,6 +14,21 @@ pub struct InputHandler {
     mouse_captured: bool,
     delta: f32,
     pub dirty: bool,
+
+    /// Scene reload was asked for
+    pub reload: bool,
+
+    /// Reloaded scenes keep the current camera instead of the one from the file
+    pub keep_camera: bool,
+
+    /// Switch to the next tile order was asked for
+    pub next_tile_order: bool,
+
+    /// Times the tile size is to be doubled, negative to halve it
+    pub tile_size_steps: i32,
+
+    /// Render threads to add, negative to remove
+    pub thread_steps: i32,
 }
 
 impl InputHandler {
@@ -27,6 +42,11 @@ impl InputHandler {
             mouse_captured: false,
             delta: 1.0,
             dirty: false,
+            reload: false,
+            keep_camera: true,
+            next_tile_order: false,
+            tile_size_steps: 0,
+            thread_steps: 0,
         }
     }
 
@@ -52,6 +72,27 @@ impl InputHandler {
                 Keycode::D => {
                     self.moving_right = true;
                 }
+                Keycode::F5 => {
+                    self.reload = true;
+                }
+                Keycode::C => {
+                    self.keep_camera = !self.keep_camera;
+                }
+                Keycode::T => {
+                    self.next_tile_order = true;
+                }
+                Keycode::LeftBracket => {
+                    self.tile_size_steps -= 1;
+                }
+                Keycode::RightBracket => {
+                    self.tile_size_steps += 1;
+                }
+                Keycode::Minus => {
+                    self.thread_steps -= 1;
+                }
+                Keycode::Equals => {
+                    self.thread_steps += 1;
+                }
                 _ => (),
             },
 
@@ -116,5 +157,9 @@ impl InputHandler {
 
     pub fn clear(&mut self) {
         self.dirty = false;
+        self.reload = false;
+        self.next_tile_order = false;
+        self.tile_size_steps = 0;
+        self.thread_steps = 0;
     }
 }
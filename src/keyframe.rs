@@ -0,0 +1,243 @@
+use crate::math::Vec3f;
+use crate::na::zero;
+
+use serde::{Deserialize, Serialize};
+
+/// Scene property driven by a track. Objects are referred to by name and
+/// lights by their index in the scene. Scalar properties use the `x`
+/// component of the keyframe value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Property {
+    Position(String),
+    /// Euler angles around x, y and z in degrees
+    Rotation(String),
+    /// Spheres stay round and take the largest of the three factors
+    Scale(String),
+    Ambient(String),
+    Diffuse(String),
+    Specular(String),
+    Shininess(String),
+    Reflection(String),
+    LightPosition(usize),
+    LightColor(usize),
+    CameraEye,
+    CameraFov,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// Holds the value until the next keyframe
+    Step,
+    #[default]
+    Linear,
+    /// Cubic Bézier through keyframe tangent handles
+    Bezier,
+    /// Smooth curve through all keyframes
+    CatmullRom,
+}
+
+/// Remaps time between two keyframes
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+/// What happens after the last keyframe
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Repeat {
+    /// Holds the last value
+    #[default]
+    Once,
+    Loop,
+    PingPong,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keyframe {
+    /// Seconds since the animation start
+    pub time: f32,
+    pub value: Vec3f,
+
+    /// Bézier handle towards the previous keyframe, relative to `value`
+    #[serde(default = "zero")]
+    pub in_tangent: Vec3f,
+
+    /// Bézier handle towards the next keyframe, relative to `value`
+    #[serde(default = "zero")]
+    pub out_tangent: Vec3f,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Track {
+    pub property: Property,
+    /// Sorted by time
+    pub keys: Vec<Keyframe>,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    #[serde(default)]
+    pub easing: Easing,
+    #[serde(default)]
+    pub repeat: Repeat,
+}
+
+impl Easing {
+    fn apply(self, u: f32) -> f32 {
+        match self {
+            Easing::Linear => u,
+            Easing::EaseIn => u * u,
+            Easing::EaseOut => u * (2. - u),
+            Easing::EaseInOut => u * u * (3. - 2. * u),
+        }
+    }
+}
+
+impl Track {
+    /// Maps `t` into the keyframe time range according to the repeat mode
+    fn local_time(&self, t: f32) -> f32 {
+        let start = self.keys[0].time;
+        let end = self.keys[self.keys.len() - 1].time;
+        let duration = end - start;
+        if duration <= 0. {
+            return start;
+        }
+        match self.repeat {
+            Repeat::Once => t.max(start).min(end),
+            Repeat::Loop => start + (t - start).rem_euclid(duration),
+            Repeat::PingPong => {
+                let phase = (t - start).rem_euclid(2. * duration);
+                if phase > duration {
+                    start + 2. * duration - phase
+                } else {
+                    start + phase
+                }
+            }
+        }
+    }
+
+    /// Value of the property at `t` seconds, `None` for a track without keyframes
+    pub fn sample(&self, t: f32) -> Option<Vec3f> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let t = self.local_time(t);
+        let next = match self.keys.iter().position(|k| k.time > t) {
+            Some(0) => return Some(self.keys[0].value),
+            Some(i) => i,
+            None => return Some(self.keys[self.keys.len() - 1].value),
+        };
+        let (k0, k1) = (&self.keys[next - 1], &self.keys[next]);
+        let u = self.easing.apply((t - k0.time) / (k1.time - k0.time));
+
+        let value = match self.interpolation {
+            Interpolation::Step => k0.value,
+            Interpolation::Linear => k0.value + (k1.value - k0.value) * u,
+            Interpolation::Bezier => {
+                let p1 = k0.value + k0.out_tangent;
+                let p2 = k1.value + k1.in_tangent;
+                let v = 1. - u;
+                k0.value * (v * v * v)
+                    + p1 * (3. * v * v * u)
+                    + p2 * (3. * v * u * u)
+                    + k1.value * (u * u * u)
+            }
+            Interpolation::CatmullRom => {
+                let p0 = self.keys[next.saturating_sub(2)].value;
+                let p3 = self.keys[(next + 1).min(self.keys.len() - 1)].value;
+                let (p1, p2) = (k0.value, k1.value);
+                let (u2, u3) = (u * u, u * u * u);
+                (p1 * 2.
+                    + (p2 - p0) * u
+                    + (p0 * 2. - p1 * 5. + p2 * 4. - p3) * u2
+                    + (p1 * 3. - p0 - p2 * 3. + p3) * u3)
+                    * 0.5
+            }
+        };
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(values: &[(f32, f32)], easing: Easing, repeat: Repeat) -> Track {
+        Track {
+            property: Property::CameraFov,
+            keys: values
+                .iter()
+                .map(|&(time, x)| Keyframe {
+                    time,
+                    value: Vec3f::new(x, 0., 0.),
+                    in_tangent: zero(),
+                    out_tangent: zero(),
+                })
+                .collect(),
+            interpolation: Interpolation::Linear,
+            easing,
+            repeat,
+        }
+    }
+
+    fn x(track: &Track, t: f32) -> f32 {
+        track.sample(t).unwrap().x
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn empty() {
+        assert!(track(&[], Easing::Linear, Repeat::Once)
+            .sample(1.)
+            .is_none());
+    }
+
+    #[test]
+    fn easing() {
+        let keys = [(0., 0.), (2., 10.)];
+        let linear = track(&keys, Easing::Linear, Repeat::Once);
+        assert!(close(x(&linear, 0.5), 2.5));
+        assert!(close(
+            x(&track(&keys, Easing::EaseIn, Repeat::Once), 0.5),
+            0.625
+        ));
+        assert!(close(
+            x(&track(&keys, Easing::EaseOut, Repeat::Once), 0.5),
+            4.375
+        ));
+        let in_out = track(&keys, Easing::EaseInOut, Repeat::Once);
+        assert!(close(x(&in_out, 1.), 5.));
+        assert!(x(&in_out, 0.5) < 2.5);
+        assert!(x(&in_out, 1.5) > 7.5);
+    }
+
+    #[test]
+    fn repeat() {
+        let keys = [(1., 0.), (3., 10.)];
+        let once = track(&keys, Easing::Linear, Repeat::Once);
+        assert!(close(x(&once, 0.), 0.));
+        assert!(close(x(&once, 2.), 5.));
+        assert!(close(x(&once, 10.), 10.));
+
+        let looped = track(&keys, Easing::Linear, Repeat::Loop);
+        assert!(close(x(&looped, 4.), 5.));
+        assert!(close(x(&looped, 0.), 5.));
+
+        let ping_pong = track(&keys, Easing::Linear, Repeat::PingPong);
+        assert!(close(x(&ping_pong, 4.), 5.));
+        assert!(close(x(&ping_pong, 4.5), 2.5));
+        assert!(close(x(&ping_pong, 5.5), 2.5));
+    }
+
+    #[test]
+    fn single_key() {
+        let single = track(&[(2., 7.)], Easing::Linear, Repeat::Loop);
+        assert!(close(x(&single, 0.), 7.));
+        assert!(close(x(&single, 5.), 7.));
+    }
+}
@@ -1,12 +1,26 @@
-use crate::animation::SetPosition;
+use crate::animation::{Motion, SetPosition};
 use crate::math::Vec3f;
 
 use serde::{Serialize, Deserialize};
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Light {
     pub pos: Vec3f,
     pub color: Vec3f,
+
+    #[serde(default)]
+    pub motion: Option<Motion>,
+}
+
+impl Light {
+    /// Moves the light to where its motion puts it at `t` seconds
+    pub fn evaluate_motion(&mut self, t: f32) {
+        let pos = match self.motion {
+            None => return,
+            Some(ref m) => m.position_at(t),
+        };
+        self.set_position(pos);
+    }
 }
 
 impl SetPosition for Light {
@@ -2,29 +2,85 @@ extern crate sdl2;
 extern crate serde;
 extern crate nalgebra;
 extern crate ron;
+extern crate gltf;
+extern crate image;
 extern crate scoped_threadpool;
 extern crate num_traits;
 extern crate num_cpus;
 
 mod animation;
 mod app;
+mod builtin;
 mod camera;
+mod cli;
+mod compare;
+mod composition;
+mod export;
 mod fps_counter;
 mod geometry;
+mod gltf_import;
+#[cfg(test)]
+mod golden;
+mod image_io;
 mod input;
+mod keyframe;
 mod light;
 mod material;
 mod math;
+mod mesh_import;
+mod migration;
 mod object;
+mod pbrt_import;
 mod raytracer;
+mod render_settings;
+mod renderer;
 mod resource;
+mod sampling;
 mod surface;
 mod scene;
 mod scene_generator;
+mod sequence;
+mod validation;
+mod watcher;
 mod font;
 
 use nalgebra as na;
 
+use self::cli::Command;
+
 fn main() {
-    self::app::run()
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match cli::parse(&args) {
+        Ok(Command::View {
+            scene,
+            settings,
+            renderer,
+        }) => self::app::run(&scene, &settings, &renderer),
+        Ok(Command::Render(opts)) => self::sequence::render(&opts),
+        Ok(Command::Migrate { files, out }) => {
+            self::migration::migrate_files(&files, out.as_deref())
+        }
+        Ok(Command::Import { file, out }) => self::scene::convert(&file, out.as_deref()),
+        Ok(Command::Export { file, out, time }) => self::export::export(&file, &out, time),
+        Ok(Command::Generate {
+            template,
+            count,
+            seed,
+            out,
+        }) => self::scene_generator::write(template, count, seed, out.as_deref()),
+        Ok(Command::Compare {
+            first,
+            second,
+            out,
+            max,
+        }) => self::compare::compare(&first, &second, out.as_deref(), max),
+        Err(msg) => {
+            eprintln!("{}\n\n{}", msg, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(msg) = result {
+        eprintln!("{}", msg);
+        std::process::exit(1);
+    }
 }
@@ -3,7 +3,9 @@ use crate::raytracer::Ray;
 use crate::math::Vec3f;
 use serde::{Serialize, Deserialize};
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
 pub struct Material {
     pub ambient: Vec3f,
     pub diffuse: Vec3f,
@@ -13,6 +15,29 @@ pub struct Material {
     pub refraction: f32,
 }
 
+/// Material of an object as written in the scene file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MaterialRef {
+    /// Name of a material in the scene library
+    Named(String),
+    Inline(Material),
+}
+
+/// Materials by name, as kept in a scene or a library file
+pub type MaterialLibrary = BTreeMap<String, Material>;
+
+/// Phong exponent giving highlights about as wide as those of a microfacet
+/// surface with the perceptual `roughness`
+pub fn roughness_to_shininess(roughness: f32) -> f32 {
+    (2. / roughness.max(1e-3).powi(4) - 2.).clamp(1., 1000.)
+}
+
+/// Inverse of `roughness_to_shininess`
+pub fn shininess_to_roughness(shininess: f32) -> f32 {
+    (2. / (shininess.max(0.) + 2.)).powf(0.25)
+}
+
 pub struct Hit<'a> {
     pub ray: &'a Ray,
     pub tnear: f32,
@@ -36,50 +61,44 @@ fn clamp(f: Vec3f) -> Vec3f {
 }
 
 impl Material {
-    pub fn compute_color(
-        &self,
-        hit: &Hit,
-        light: &Light,
-        reflected_color: Vec3f,
-        refracted_color: Vec3f,
-        light_shaded: bool,
-    ) -> Vec3f {
-        let mul = |l: &Vec3f, r: &Vec3f| -> Vec3f {
-            Vec3f::new(l.x * r.x, l.y * r.y, l.z * r.z)
-        };
+    /// Material with the diffuse color multiplied by `color`
+    pub fn tinted(&self, color: Vec3f) -> Material {
+        Material {
+            diffuse: self.diffuse.component_mul(&color),
+            ..*self
+        }
+    }
 
+    /// Diffuse and specular light reaching the hit point straight from `light`
+    pub fn direct(&self, hit: &Hit, light: &Light) -> Vec3f {
         let ray = hit.ray;
-        let tnear = hit.tnear;
         let nhit = hit.nhit;
 
-        let phit = ray.origin + ray.dir * tnear;
+        let phit = ray.origin + ray.dir * hit.tnear;
         let ldir = (light.pos - phit).normalize();
         let ndotl = nhit.dot(&ldir);
-        let lambert = mul(&light.color, &self.diffuse) * max(ndotl, 0.0);
+        let lambert = light.color.component_mul(&self.diffuse) * max(ndotl, 0.0);
 
         let halfv = (-ray.dir + ldir).normalize();
         let ndoth = nhit.dot(&halfv);
-        let phong = mul(&light.color, &self.specular) * max(ndoth, 0.0).powf(self.shininess);
+        let phong =
+            light.color.component_mul(&self.specular) * max(ndoth, 0.0).powf(self.shininess);
+
+        lambert + phong
+    }
 
-        if self.reflection == 0.0 {
-            if light_shaded {
-                clamp(mul(&self.ambient, &self.diffuse))
-            } else {
-                clamp(lambert + phong + self.ambient)
-            }
-        } else if light_shaded {
-            clamp(
-                mul(&self.ambient, &self.diffuse)
-                    + reflected_color * self.reflection
-                    + refracted_color * (1.0 - self.reflection),
-            )
-        } else {
-            clamp(
-                phong
-                    + lambert
-                    + reflected_color * self.reflection
-                    + refracted_color * (1.0 - self.reflection),
-            )
+    /// Final color of a hit from the direct light summed over the unshaded
+    /// lights and the colors seen along the reflected and refracted rays
+    pub fn compute_color(
+        &self,
+        direct: Vec3f,
+        reflected_color: Vec3f,
+        refracted_color: Vec3f,
+    ) -> Vec3f {
+        let mut color = self.ambient.component_mul(&self.diffuse) + direct;
+        if self.reflection > 0.0 {
+            color += reflected_color * self.reflection + refracted_color * (1.0 - self.reflection);
         }
+        clamp(color)
     }
 }
@@ -3,6 +3,7 @@ type FloatType = f32;
 pub use crate::na::Vector3 as Vec3;
 pub type Vec3f = Vec3<FloatType>;
 
+use crate::na::{Matrix3, Point3, Rotation3};
 use crate::na::Matrix4 as Mat4;
 pub type Mat4f = Mat4<FloatType>;
 
@@ -16,3 +17,54 @@ pub fn set_translation(transform: &mut Mat4f, translation: Vec3f) {
     transform[(1, 3)] = translation.y;
     transform[(2, 3)] = translation.z;
 }
+
+pub fn transform_point(transform: &Mat4f, p: Vec3f) -> Vec3f {
+    transform.transform_point(&Point3::from(p)).coords
+}
+
+/// Inverse of the transform, `None` if it squashes space flat. A pure
+/// translation, the usual case, is inverted without the full computation
+pub fn inverse(transform: &Mat4f) -> Option<Mat4f> {
+    if transform.fixed_view::<3, 3>(0, 0) == Matrix3::identity() {
+        Some(Mat4f::new_translation(&-translation(transform)))
+    } else {
+        transform.try_inverse()
+    }
+}
+
+/// Length of the transform basis vectors
+pub fn scale(transform: &Mat4f) -> Vec3f {
+    Vec3f::new(
+        transform.fixed_view::<3, 1>(0, 0).norm(),
+        transform.fixed_view::<3, 1>(0, 1).norm(),
+        transform.fixed_view::<3, 1>(0, 2).norm(),
+    )
+}
+
+/// Replaces the rotation part of the transform keeping its scale and translation,
+/// `angles` are Euler angles around x, y and z in degrees
+pub fn set_rotation(transform: &mut Mat4f, angles: Vec3f) {
+    let s = scale(transform);
+    let rot = Rotation3::from_euler_angles(
+        angles.x.to_radians(),
+        angles.y.to_radians(),
+        angles.z.to_radians(),
+    );
+    let basis = rot.matrix() * Matrix3::from_diagonal(&s);
+    transform.fixed_view_mut::<3, 3>(0, 0).copy_from(&basis);
+}
+
+/// Replaces the scale part of the transform keeping its rotation and translation
+pub fn set_scale(transform: &mut Mat4f, new_scale: Vec3f) {
+    let s = scale(transform);
+    for i in 0..3 {
+        let mut column = transform.fixed_view_mut::<3, 1>(0, i);
+        if s[i] > 0. {
+            column *= new_scale[i] / s[i];
+        } else {
+            // Degenerate axis has no direction left, fall back to the unrotated one
+            column.fill(0.);
+            column[i] = new_scale[i];
+        }
+    }
+}
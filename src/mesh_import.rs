@@ -0,0 +1,467 @@
+//! Triangle meshes from PLY and STL files
+
+use crate::geometry::Triangle;
+use crate::math::Vec3f;
+use crate::scene::SceneError;
+
+use std::path::Path;
+use std::str::SplitAsciiWhitespace;
+
+/// Triangles of a mesh file, in the coordinates of the file
+pub struct Mesh {
+    pub triangles: Vec<Triangle>,
+    /// Triangles left out because their corners are on one line
+    pub degenerate: usize,
+}
+
+impl Mesh {
+    fn new() -> Mesh {
+        Mesh {
+            triangles: Vec::new(),
+            degenerate: 0,
+        }
+    }
+
+    fn push(&mut self, corners: [Vec3f; 3], colors: Option<(Vec3f, Vec3f, Vec3f)>) {
+        let [a, b, c] = corners;
+        if (b - a).cross(&(c - a)).norm() < 1e-8 {
+            self.degenerate += 1;
+        } else {
+            self.triangles.push(Triangle { a, b, c, colors });
+        }
+    }
+}
+
+/// Reads a PLY or STL file, the format is chosen by the extension
+pub fn load(path: &Path) -> Result<Mesh, SceneError> {
+    let data = std::fs::read(path).map_err(|error| SceneError::Io {
+        file: path.to_path_buf(),
+        error,
+    })?;
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let mesh = if ext.eq_ignore_ascii_case("ply") {
+        ply(&data)
+    } else if ext.eq_ignore_ascii_case("stl") {
+        stl(&data)
+    } else {
+        Err("unknown mesh format, expected .ply or .stl".to_string())
+    };
+    mesh.map_err(|message| SceneError::Import {
+        file: path.to_path_buf(),
+        message,
+    })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Scalar, String> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(format!("unknown property type \"{}\"", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match *self {
+            Property::Scalar(ref name, _) | Property::List(ref name, _, _) => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Data after the PLY header
+enum Body<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary {
+        data: &'a [u8],
+        pos: usize,
+        big_endian: bool,
+    },
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, ty: Scalar) -> Result<f64, String> {
+        match *self {
+            Body::Ascii(ref mut tokens) => {
+                let token = tokens.next().ok_or("file ends too early")?;
+                token
+                    .parse()
+                    .map_err(|_| format!("bad number \"{}\"", token))
+            }
+            Body::Binary {
+                data,
+                ref mut pos,
+                big_endian,
+            } => {
+                let size = ty.size();
+                let mut bytes = [0u8; 8];
+                let src = data.get(*pos..*pos + size).ok_or("file ends too early")?;
+                bytes[..size].copy_from_slice(src);
+                if big_endian {
+                    bytes[..size].reverse();
+                }
+                *pos += size;
+                let b2 = [bytes[0], bytes[1]];
+                let b4 = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                Ok(match ty {
+                    Scalar::I8 => f64::from(bytes[0] as i8),
+                    Scalar::U8 => f64::from(bytes[0]),
+                    Scalar::I16 => f64::from(i16::from_le_bytes(b2)),
+                    Scalar::U16 => f64::from(u16::from_le_bytes(b2)),
+                    Scalar::I32 => f64::from(i32::from_le_bytes(b4)),
+                    Scalar::U32 => f64::from(u32::from_le_bytes(b4)),
+                    Scalar::F32 => f64::from(f32::from_le_bytes(b4)),
+                    Scalar::F64 => f64::from_le_bytes(bytes),
+                })
+            }
+        }
+    }
+}
+
+fn header_end(data: &[u8]) -> Option<usize> {
+    let marker = b"end_header";
+    let start = data.windows(marker.len()).position(|w| w == marker)?;
+    let newline = data[start..].iter().position(|&b| b == b'\n')?;
+    Some(start + newline + 1)
+}
+
+fn ply(data: &[u8]) -> Result<Mesh, String> {
+    if !data.starts_with(b"ply") {
+        return Err("not a PLY file".to_string());
+    }
+    let end = header_end(data).ok_or("no end_header line")?;
+    let header = std::str::from_utf8(&data[..end]).map_err(|_| "header is not text")?;
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in header.lines().skip(1) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", f, _] => {
+                format = Some(match *f {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(format!("unknown format \"{}\"", f)),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("bad element count \"{}\"", count))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements.last_mut().ok_or("property before any element")?;
+                let list = Property::List(
+                    name.to_string(),
+                    Scalar::parse(count)?,
+                    Scalar::parse(item)?,
+                );
+                element.properties.push(list);
+            }
+            ["property", ty, name] => {
+                let element = elements.last_mut().ok_or("property before any element")?;
+                element
+                    .properties
+                    .push(Property::Scalar(name.to_string(), Scalar::parse(ty)?));
+            }
+            ["comment", ..] | ["obj_info", ..] | ["end_header"] | [] => (),
+            _ => return Err(format!("unexpected header line \"{}\"", line)),
+        }
+    }
+
+    let mut body = match format.ok_or("no format line")? {
+        Format::Ascii => {
+            let text = std::str::from_utf8(&data[end..]).map_err(|_| "ascii data is not text")?;
+            Body::Ascii(text.split_ascii_whitespace())
+        }
+        Format::LittleEndian => Body::Binary {
+            data: &data[end..],
+            pos: 0,
+            big_endian: false,
+        },
+        Format::BigEndian => Body::Binary {
+            data: &data[end..],
+            pos: 0,
+            big_endian: true,
+        },
+    };
+
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    let mut mesh = Mesh::new();
+    for element in &elements {
+        let index = |name: &str| element.properties.iter().position(|p| p.name() == name);
+        let xyz = [index("x"), index("y"), index("z")];
+        let rgb = [index("red"), index("green"), index("blue")];
+        let indices = index("vertex_indices").or_else(|| index("vertex_index"));
+
+        let mut values = vec![0.; element.properties.len()];
+        let mut list = Vec::new();
+        for _ in 0..element.count {
+            for (i, property) in element.properties.iter().enumerate() {
+                match *property {
+                    Property::Scalar(_, ty) => values[i] = body.read(ty)?,
+                    Property::List(_, count, item) => {
+                        let n = body.read(count)? as usize;
+                        let items = (0..n)
+                            .map(|_| body.read(item))
+                            .collect::<Result<Vec<f64>, String>>()?;
+                        if Some(i) == indices {
+                            list = items;
+                        }
+                    }
+                }
+            }
+
+            if element.name == "vertex" {
+                let get = |i: Option<usize>| i.map_or(0., |i| values[i] as f32);
+                positions.push(Vec3f::new(get(xyz[0]), get(xyz[1]), get(xyz[2])));
+                if let [Some(r), Some(g), Some(b)] = rgb {
+                    // Integer channels go from 0 to the type maximum, floats from 0 to 1
+                    let scale = match element.properties[r] {
+                        Property::Scalar(_, Scalar::U8) => 255.,
+                        Property::Scalar(_, Scalar::U16) => 65535.,
+                        _ => 1.,
+                    };
+                    colors.push(Vec3f::new(get(Some(r)), get(Some(g)), get(Some(b))) / scale);
+                }
+            } else if element.name == "face" {
+                if indices.is_none() {
+                    return Err("faces have no vertex_indices".to_string());
+                }
+                let corners = list
+                    .iter()
+                    .map(|&i| i as usize)
+                    .map(|i| {
+                        if i < positions.len() {
+                            Ok(i)
+                        } else {
+                            Err(format!("vertex {} out of range", i))
+                        }
+                    })
+                    .collect::<Result<Vec<usize>, String>>()?;
+                // Polygons are split into a fan around the first corner
+                for k in 2..corners.len() {
+                    let (a, b, c) = (corners[0], corners[k - 1], corners[k]);
+                    let tint = if colors.is_empty() {
+                        None
+                    } else {
+                        Some((colors[a], colors[b], colors[c]))
+                    };
+                    mesh.push([positions[a], positions[b], positions[c]], tint);
+                }
+            }
+        }
+    }
+    Ok(mesh)
+}
+
+fn stl(data: &[u8]) -> Result<Mesh, String> {
+    // Binary files have an 80 byte header, a triangle count and 50 bytes per triangle,
+    // ascii files may start with "solid" too so the size is what tells them apart
+    if data.len() >= 84 {
+        let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+        if data.len() == 84 + count * 50 {
+            return Ok(stl_binary(&data[84..], count));
+        }
+    }
+    let text = std::str::from_utf8(data).map_err(|_| "neither a binary nor an ascii STL file")?;
+    if !text.trim_start().starts_with("solid") {
+        return Err("not an STL file".to_string());
+    }
+
+    let mut mesh = Mesh::new();
+    let mut corners = Vec::new();
+    let mut tokens = text.split_ascii_whitespace();
+    while let Some(token) = tokens.next() {
+        if token != "vertex" {
+            continue;
+        }
+        let mut xyz = [0f32; 3];
+        for v in &mut xyz {
+            let token = tokens.next().ok_or("file ends too early")?;
+            *v = token
+                .parse()
+                .map_err(|_| format!("bad number \"{}\"", token))?;
+        }
+        corners.push(Vec3f::new(xyz[0], xyz[1], xyz[2]));
+        if corners.len() == 3 {
+            mesh.push([corners[0], corners[1], corners[2]], None);
+            corners.clear();
+        }
+    }
+    Ok(mesh)
+}
+
+fn stl_binary(data: &[u8], count: usize) -> Mesh {
+    let float =
+        |at: usize| f32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+    let vertex = |at: usize| Vec3f::new(float(at), float(at + 4), float(at + 8));
+    let mut mesh = Mesh::new();
+    for i in 0..count {
+        // The normal comes first and is recomputed from the corners anyway
+        let at = i * 50 + 12;
+        mesh.push([vertex(at), vertex(at + 12), vertex(at + 24)], None);
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: [[f32; 3]; 4] = [[0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]];
+
+    fn binary_ply(format: &str, big_endian: bool) -> Vec<u8> {
+        let mut data = format!(
+            "ply\nformat {} 1.0\nelement vertex 4\nproperty float x\nproperty float y\n\
+             property float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n",
+            format
+        )
+        .into_bytes();
+        for v in &SQUARE {
+            for c in v {
+                let bytes = if big_endian {
+                    c.to_be_bytes()
+                } else {
+                    c.to_le_bytes()
+                };
+                data.extend_from_slice(&bytes);
+            }
+        }
+        data.push(4);
+        for i in 0..4i32 {
+            let bytes = if big_endian {
+                i.to_be_bytes()
+            } else {
+                i.to_le_bytes()
+            };
+            data.extend_from_slice(&bytes);
+        }
+        data
+    }
+
+    fn corners(t: &Triangle) -> [Vec3f; 3] {
+        [t.a, t.b, t.c]
+    }
+
+    fn check_square(mesh: &Mesh) {
+        let v = |i: usize| Vec3f::new(SQUARE[i][0], SQUARE[i][1], SQUARE[i][2]);
+        assert_eq!(mesh.triangles.len(), 2);
+        assert_eq!(corners(&mesh.triangles[0]), [v(0), v(1), v(2)]);
+        assert_eq!(corners(&mesh.triangles[1]), [v(0), v(2), v(3)]);
+    }
+
+    #[test]
+    fn ply_ascii_with_colors() {
+        let text = "ply\nformat ascii 1.0\ncomment square\nelement vertex 4\n\
+                    property float x\nproperty float y\nproperty float z\n\
+                    property uchar red\nproperty uchar green\nproperty uchar blue\n\
+                    element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+                    0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n4 0 1 2 3\n";
+        let mesh = ply(text.as_bytes()).unwrap();
+        check_square(&mesh);
+        let (a, b, _) = mesh.triangles[0].colors.unwrap();
+        assert_eq!(a, Vec3f::new(1., 0., 0.));
+        assert_eq!(b, Vec3f::new(0., 1., 0.));
+    }
+
+    #[test]
+    fn ply_binary() {
+        check_square(&ply(&binary_ply("binary_little_endian", false)).unwrap());
+        check_square(&ply(&binary_ply("binary_big_endian", true)).unwrap());
+    }
+
+    #[test]
+    fn ply_errors() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+                      property float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n";
+        let degenerate = format!("{}0 0 0\n1 1 1\n2 2 2\n3 0 1 2\n", header);
+        let mesh = ply(degenerate.as_bytes()).unwrap();
+        assert_eq!((mesh.triangles.len(), mesh.degenerate), (0, 1));
+
+        let out_of_range = format!("{}0 0 0\n1 0 0\n0 1 0\n3 0 1 7\n", header);
+        assert!(ply(out_of_range.as_bytes()).is_err());
+        let truncated = format!("{}0 0 0\n1 0 0\n", header);
+        assert!(ply(truncated.as_bytes()).is_err());
+        assert!(ply(b"ply\nformat ascii 1.0\n").is_err());
+    }
+
+    #[test]
+    fn stl_ascii() {
+        let text = "solid square\n\
+                    facet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 1 1 0\nendloop\nendfacet\n\
+                    facet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 1 0\nvertex 0 1 0\nendloop\nendfacet\n\
+                    endsolid square\n";
+        check_square(&stl(text.as_bytes()).unwrap());
+        assert!(stl(b"solid\nvertex 0 0 x\n").is_err());
+        assert!(stl(b"nothing").is_err());
+    }
+
+    #[test]
+    fn stl_binary_file() {
+        // Header that starts like an ascii file to check the size decides
+        let mut data = b"solid binary".to_vec();
+        data.resize(80, 0);
+        data.extend_from_slice(&2u32.to_le_bytes());
+        for face in &[[0, 1, 2], [0, 2, 3]] {
+            data.extend_from_slice(&[0; 12]);
+            for &i in face {
+                for c in &SQUARE[i] {
+                    data.extend_from_slice(&c.to_le_bytes());
+                }
+            }
+            data.extend_from_slice(&[0; 2]);
+        }
+        check_square(&stl(&data).unwrap());
+    }
+}
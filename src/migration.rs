@@ -0,0 +1,373 @@
+//! Older scene layouts and their conversion into the current one.
+//!
+//! * version 0 -- `.rts` files: no camera, untagged shapes, vectors and
+//!   matrices written as arrays
+//! * version 1 -- camera added, tagged shapes, vectors written as tuples,
+//!   animations move by a fixed `factor` every frame
+//! * version 2 -- explicit `version` field, animation `speed` in units per second
+//! * version 3 -- named materials and libraries, includes, prefabs, object
+//!   children, meshes and render settings. Version 2 files read as they are
+//!
+//! Every change to the layout bumps the version, added fields too, so a
+//! file using them is refused by older builds instead of silently misread.
+
+use crate::animation::Animation;
+use crate::camera::{CamBuilder, Camera};
+use crate::geometry::{Cuboid, Geometry, Sphere, Triangle};
+use crate::light::Light;
+use crate::material::{Material, MaterialRef};
+use crate::math::{Mat4f, Vec3f};
+use crate::object::Object;
+use crate::scene::Scene;
+
+use ron::error::{Position, Span, SpannedError, SpannedResult};
+use ron::ser::{to_string_pretty, PrettyConfig};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer};
+
+pub const CURRENT_VERSION: u32 = 3;
+
+/// Viewer frame rate version 1 animations were tuned for
+const LEGACY_FPS: f32 = 30.;
+
+fn present<'de, D: Deserializer<'de>>(d: D) -> Result<bool, D::Error> {
+    IgnoredAny::deserialize(d).map(|_| true)
+}
+
+/// Just enough of a scene to tell its version
+#[derive(Deserialize)]
+struct Probe {
+    #[serde(default)]
+    version: u32,
+    #[serde(default, deserialize_with = "present")]
+    cam: bool,
+}
+
+#[derive(Deserialize)]
+struct AnimationV1 {
+    path_lengts: Vec<f32>,
+    control_points: Vec<Vec3f>,
+    factor: f32,
+}
+
+impl AnimationV1 {
+    fn upgrade(self) -> Animation {
+        Animation::from_control_points(
+            self.control_points,
+            self.path_lengts,
+            self.factor * LEGACY_FPS,
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct ObjectV1 {
+    name: String,
+    shapes: Vec<Geometry>,
+    mat: Material,
+    transform: Mat4f,
+    animation: Option<AnimationV1>,
+}
+
+#[derive(Deserialize)]
+struct SceneV1 {
+    cam: Camera,
+    objects: Vec<ObjectV1>,
+    lights: Vec<Light>,
+}
+
+fn vec3(v: &[f32]) -> Vec3f {
+    let at = |i| v.get(i).cloned().unwrap_or(0.);
+    Vec3f::new(at(0), at(1), at(2))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GeometryV0 {
+    Triangle {
+        a: Vec<f32>,
+        b: Vec<f32>,
+        c: Vec<f32>,
+    },
+    Cuboid {
+        extent: Vec<f32>,
+    },
+    Sphere {
+        radius: f32,
+    },
+}
+
+impl GeometryV0 {
+    fn upgrade(self) -> Geometry {
+        match self {
+            GeometryV0::Triangle { a, b, c } => Geometry::Triangle(Triangle {
+                a: vec3(&a),
+                b: vec3(&b),
+                c: vec3(&c),
+                colors: None,
+            }),
+            GeometryV0::Cuboid { extent } => Geometry::Cuboid(Cuboid {
+                extent: vec3(&extent),
+            }),
+            GeometryV0::Sphere { radius } => Geometry::Sphere(Sphere { radius }),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct MaterialV0 {
+    ambient: Vec<f32>,
+    diffuse: Vec<f32>,
+    specular: Vec<f32>,
+    shininess: f32,
+    reflection: f32,
+    refraction: f32,
+}
+
+#[derive(Deserialize)]
+struct AnimationV0 {
+    path_lengts: Vec<f32>,
+    control_points: Vec<Vec<f32>>,
+    factor: f32,
+}
+
+#[derive(Deserialize)]
+struct ObjectV0 {
+    name: String,
+    shapes: Vec<GeometryV0>,
+    mat: MaterialV0,
+    /// Column-major 4x4 matrix
+    transform: Vec<f32>,
+    animation: Option<AnimationV0>,
+}
+
+#[derive(Deserialize)]
+struct LightV0 {
+    pos: Vec<f32>,
+    color: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct SceneV0 {
+    objects: Vec<ObjectV0>,
+    lights: Vec<LightV0>,
+}
+
+impl SceneV0 {
+    fn upgrade(self) -> SceneV1 {
+        // Files of this version were always viewed with the generator camera
+        let cam = CamBuilder::new()
+            .eye(Vec3f::new(0., 0., 60.))
+            .center(Vec3f::new(0., 0., 59.))
+            .fov(30.)
+            .width(128)
+            .height(128)
+            .up(Vec3f::new(0., -1., 0.))
+            .build();
+        let objects = self
+            .objects
+            .into_iter()
+            .map(|o| {
+                let mut transform = Mat4f::identity();
+                if o.transform.len() == 16 {
+                    transform.copy_from_slice(&o.transform);
+                }
+                ObjectV1 {
+                    name: o.name,
+                    shapes: o.shapes.into_iter().map(GeometryV0::upgrade).collect(),
+                    mat: Material {
+                        ambient: vec3(&o.mat.ambient),
+                        diffuse: vec3(&o.mat.diffuse),
+                        specular: vec3(&o.mat.specular),
+                        shininess: o.mat.shininess,
+                        reflection: o.mat.reflection,
+                        refraction: o.mat.refraction,
+                    },
+                    transform,
+                    animation: o.animation.map(|a| AnimationV1 {
+                        path_lengts: a.path_lengts,
+                        control_points: a.control_points.iter().map(|p| vec3(p)).collect(),
+                        factor: a.factor,
+                    }),
+                }
+            })
+            .collect();
+        let lights = self
+            .lights
+            .into_iter()
+            .map(|l| Light {
+                pos: vec3(&l.pos),
+                color: vec3(&l.color),
+                motion: None,
+            })
+            .collect();
+        SceneV1 {
+            cam,
+            objects,
+            lights,
+        }
+    }
+}
+
+impl SceneV1 {
+    fn upgrade(self) -> Scene {
+        let objects = self
+            .objects
+            .into_iter()
+            .map(|o| Object {
+                name: o.name,
+                shapes: o.shapes,
+                mesh: None,
+                material: MaterialRef::Inline(o.mat),
+                mat: o.mat,
+                transform: o.transform,
+                animation: o.animation.map(AnimationV1::upgrade),
+                children: Vec::new(),
+                parent: None,
+                world: o.transform,
+                prev_transform: None,
+            })
+            .collect();
+        Scene::new(self.cam, objects, self.lights)
+    }
+}
+
+/// Version the scene text is written in
+pub fn detect_version(text: &str) -> SpannedResult<u32> {
+    let probe: Probe = ron::de::from_str(text)?;
+    Ok(match probe.version {
+        0 if probe.cam => 1,
+        v => v,
+    })
+}
+
+/// Parses a scene of any known version, upgrading it to the current one
+pub fn parse(text: &str) -> SpannedResult<Scene> {
+    match detect_version(text)? {
+        0 => Ok(ron::de::from_str::<SceneV0>(text)?.upgrade().upgrade()),
+        1 => Ok(ron::de::from_str::<SceneV1>(text)?.upgrade()),
+        2 => {
+            let mut scene: Scene = ron::de::from_str(text)?;
+            scene.version = CURRENT_VERSION;
+            Ok(scene)
+        }
+        CURRENT_VERSION => ron::de::from_str(text),
+        v => {
+            let start = Position { line: 1, col: 1 };
+            Err(SpannedError {
+                code: ron::Error::Message(format!(
+                    "scene version {} is newer than supported {}",
+                    v, CURRENT_VERSION
+                )),
+                span: Span { start, end: start },
+            })
+        }
+    }
+}
+
+/// Scene text in the current format
+pub fn serialize(scene: &Scene) -> Result<String, ron::Error> {
+    to_string_pretty(scene, PrettyConfig::default())
+}
+
+/// Rewrites scene files in the current format, in place unless `out` is given
+pub fn migrate_files(files: &[String], out: Option<&str>) -> Result<(), String> {
+    for file in files {
+        let text =
+            std::fs::read_to_string(file).map_err(|e| format!("Can't read {}: {}", file, e))?;
+        let version = detect_version(&text).map_err(|e| format!("{}:{}", file, e))?;
+        let scene = parse(&text).map_err(|e| format!("{}:{}", file, e))?;
+        let migrated = serialize(&scene).map_err(|e| format!("{}: {}", file, e))?;
+        let target = out.unwrap_or(file);
+        std::fs::write(target, migrated).map_err(|e| format!("Can't write {}: {}", target, e))?;
+        println!(
+            "{}: version {} -> {}, written to {}",
+            file, version, CURRENT_VERSION, target
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::translation;
+
+    const V0: &str = "(
+        objects: [(
+            name: \"ball\",
+            shapes: [(radius: 5)],
+            mat: (ambient: [0.1, 0.1, 0.1], diffuse: [0, 1, 0.3], specular: [1, 1, 1],
+                  shininess: 10, reflection: 0.8, refraction: 0),
+            transform: [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 15, 15, 15, 1],
+            animation: Some((path_lengts: [10, 10], control_points: [[15, 15, 15], [15, 5, 15]], factor: 0.1)),
+        )],
+        lights: [(pos: [0, -20, 0], color: [1, 1, 1])],
+    )";
+
+    const CAMERA: &str = "cam: (eye: (0, 0, 40), dir: (0, 0, -1), up: (0, -1, 0), fov: 25,
+                          width: 64, height: 32, angles: (x: 0, y: 180))";
+
+    fn v1() -> String {
+        format!(
+            "({},
+            objects: [(
+                name: \"box\",
+                shapes: [Cuboid((extent: (1, 2, 3)))],
+                mat: (ambient: (0.1, 0.1, 0.1), diffuse: (1, 0, 0), specular: (1, 1, 1),
+                      shininess: 10, reflection: 0, refraction: 0),
+                transform: (1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 1, 2, 3, 1),
+                animation: Some((path_lengts: [10, 10], control_points: [(0, 0, 0), (0, 10, 0)], factor: 0.1)),
+            )],
+            lights: [],
+        )",
+            CAMERA
+        )
+    }
+
+    #[test]
+    fn versions() {
+        assert_eq!(detect_version(V0).unwrap(), 0);
+        assert_eq!(detect_version(&v1()).unwrap(), 1);
+        assert_eq!(detect_version("(version: 2, objects: [])").unwrap(), 2);
+        assert!(parse(&format!("(version: {}, objects: [])", CURRENT_VERSION + 1)).is_err());
+    }
+
+    #[test]
+    fn from_version_0() {
+        let scene = parse(V0).unwrap();
+        assert_eq!(scene.version, CURRENT_VERSION);
+        assert_eq!(scene.cam.eye, Vec3f::new(0., 0., 60.));
+        assert_eq!(scene.lights[0].pos, Vec3f::new(0., -20., 0.));
+        let ball = &scene.objects[0];
+        assert!(matches!(ball.shapes[0], Geometry::Sphere(Sphere { radius }) if radius == 5.));
+        assert_eq!(translation(&ball.transform), Vec3f::new(15., 15., 15.));
+        // 0.1 units a frame at 30 frames a second
+        let animation = ball.animation.as_ref().unwrap();
+        assert!((animation.position_at(1.) - Vec3f::new(15., 12., 15.)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn from_version_1() {
+        let scene = parse(&v1()).unwrap();
+        assert_eq!(scene.version, CURRENT_VERSION);
+        assert_eq!((scene.cam.width, scene.cam.height), (64, 32));
+        let b = &scene.objects[0];
+        assert!(
+            matches!(b.shapes[0], Geometry::Cuboid(Cuboid { extent }) if extent == Vec3f::new(1., 2., 3.))
+        );
+        assert_eq!(b.mat.diffuse, Vec3f::new(1., 0., 0.));
+        let animation = b.animation.as_ref().unwrap();
+        assert!((animation.position_at(1.) - Vec3f::new(0., 3., 0.)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn from_version_2_and_back() {
+        let v2 = format!("(version: 2, {}, objects: [], lights: [])", CAMERA);
+        let scene = parse(&v2).unwrap();
+        assert_eq!(scene.version, CURRENT_VERSION);
+        let text = serialize(&scene).unwrap();
+        assert_eq!(detect_version(&text).unwrap(), CURRENT_VERSION);
+        assert_eq!(parse(&text).unwrap().cam.width, 64);
+    }
+}
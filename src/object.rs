@@ -1,97 +1,120 @@
 use crate::animation::SetPosition;
-use crate::geometry::{Geometry, Sphere, Cuboid, Triangle};
-use crate::material::Material;
+use crate::geometry::Geometry;
+use crate::material::{Material, MaterialRef};
 use crate::math::{Vec3f, Mat4f, translation, set_translation};
 use crate::animation::Animation;
 
 use serde::{Serialize, Deserialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Object {
     pub name: String,
+    #[serde(default)]
     pub shapes: Vec<Geometry>,
+    /// PLY or STL file whose triangles are added to `shapes` on load
+    #[serde(default)]
+    pub mesh: Option<String>,
+    /// Material as written in the scene, inline or by name
+    #[serde(rename = "mat")]
+    pub material: MaterialRef,
+    /// Material the object is rendered with, resolved from `material` on load
+    #[serde(skip)]
     pub mat: Material,
+    /// Transform relative to the parent object
     pub transform: Mat4f,
     pub animation: Option<Animation>,
+
+    /// Objects placed relative to this one, they move along with it
+    #[serde(default)]
+    pub children: Vec<Object>,
+
+    /// Index of the parent in the scene object list, set when the tree is flattened
+    #[serde(skip)]
+    pub parent: Option<usize>,
+
+    /// `transform` composed with the parents, this is what gets rendered
+    #[serde(skip, default = "Mat4f::identity")]
+    pub world: Mat4f,
+
+    /// World transform at the beginning of the frame, used for motion blur
+    #[serde(skip)]
+    pub prev_transform: Option<Mat4f>,
 }
 
 impl Object {
-    pub fn update_animation(&mut self) {
-        let mut t: Vec3f = self.get_position();
-        match self.animation
-        {
-            None => {},
-            Some(ref mut a) => {
-                t += a.update();
-            }
-        }
-        self.set_position(t);
+    /// Moves the object to where its animation puts it at `t` seconds
+    pub fn evaluate_animation(&mut self, t: f32) {
+        let pos = match self.animation {
+            None => return,
+            Some(ref a) => a.position_at(t),
+        };
+        self.set_position(pos);
     }
 
     pub fn set_animation(&mut self, a: Animation) {
         self.animation = Some(a);
     }
-}
 
-impl SetPosition for Object {
-    fn set_position(&mut self, pos: Vec3f) {
-        set_translation(&mut self.transform, pos);
+    /// World transform at the given moment between the previous and the current frame
+    pub fn transform_at(&self, time: f32) -> Mat4f {
+        match self.prev_transform {
+            Some(prev) if time < 1.0 => prev + (self.world - prev) * time,
+            _ => self.world,
+        }
     }
 
-    fn get_position(&self) -> Vec3f {
-        translation(&self.transform)
+    /// Object without animation or children, a named material is resolved on scene load
+    pub fn new(
+        name: &str,
+        shapes: Vec<Geometry>,
+        material: MaterialRef,
+        transform: Mat4f,
+    ) -> Object {
+        let mat = match material {
+            MaterialRef::Inline(mat) => mat,
+            MaterialRef::Named(_) => Material::default(),
+        };
+        Object {
+            name: name.to_string(),
+            shapes,
+            mesh: None,
+            material,
+            mat,
+            transform,
+            animation: None,
+            children: Vec::new(),
+            parent: None,
+            world: transform,
+            prev_transform: None,
+        }
     }
 }
 
-pub fn new_sphere(name: &str, center: Vec3f, radius: f32, mat: Material) -> Object {
-    let mut t = Mat4f::identity();
-    set_translation(&mut t, center);
-    Object {
-        name: name.to_string(),
-        shapes: vec![Geometry::Sphere(Sphere { radius })],
-        mat,
-        transform: t,
-        animation: None,
+/// Turns object trees into a list where every object comes after its parent
+pub fn flatten(roots: Vec<Object>) -> Vec<Object> {
+    let mut flat = Vec::new();
+    for root in roots {
+        push_tree(&mut flat, root, None);
     }
+    flat
 }
 
-pub fn new_box(name: &str, vmin: Vec3f, vmax: Vec3f, mat: Material) -> Object {
-    let mut t = Mat4f::identity();
-    set_translation(&mut t, vmin);
-    Object {
-        name: name.to_string(),
-
-        shapes: vec![Geometry::Cuboid(Cuboid { extent: vmax })],
-        mat,
-        transform: t,
-        animation: None,
+fn push_tree(flat: &mut Vec<Object>, mut obj: Object, parent: Option<usize>) {
+    let children = std::mem::take(&mut obj.children);
+    obj.parent = parent;
+    let index = flat.len();
+    flat.push(obj);
+    for child in children {
+        push_tree(flat, child, Some(index));
     }
 }
 
-pub fn new_triangle(name: &str, a: Vec3f, b: Vec3f, c: Vec3f, mat: Material) -> Object {
-    Object {
-        name: name.to_string(),
-        shapes: vec![Geometry::Triangle(Triangle { a, b, c })],
-        mat,
-        transform: Mat4f::identity(),
-        animation: None,
+impl SetPosition for Object {
+    fn set_position(&mut self, pos: Vec3f) {
+        set_translation(&mut self.transform, pos);
     }
-}
 
-pub fn new_square(name: &str, center: Vec3f, size: u16, mat: Material) -> Object {
-    let s_2 = f32::from(size) / 2.;
-    let a = Vec3f::new(center.x - s_2, center.y, center.z - s_2);
-    let b = Vec3f::new(center.x + s_2, center.y, center.z - s_2);
-    let c = Vec3f::new(center.x + s_2, center.y, center.z + s_2);
-    let d = Vec3f::new(center.x - s_2, center.y, center.z + s_2);
-    Object {
-        name: name.to_string(),
-        shapes: vec![
-            Geometry::Triangle(Triangle { a, b, c }),
-            Geometry::Triangle(Triangle { a, b: c, c: d }),
-        ],
-        mat,
-        transform: Mat4f::identity(),
-        animation: None,
+    fn get_position(&self) -> Vec3f {
+        translation(&self.transform)
     }
 }
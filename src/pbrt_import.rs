@@ -0,0 +1,940 @@
+//! PBRT-v3 import for the common directives: camera and film, transforms,
+//! attribute blocks, spheres and triangle meshes, the basic materials and
+//! point, distant and area lights. Everything else is skipped with a warning.
+
+use crate::camera::{CamBuilder, Camera, Projection};
+use crate::geometry::{Geometry, Sphere, Triangle};
+use crate::light::Light;
+use crate::material::{roughness_to_shininess, Material, MaterialLibrary, MaterialRef};
+use crate::math::{set_translation, transform_point, Mat4f, Vec3f};
+use crate::mesh_import;
+use crate::na::{Rotation3, Unit};
+use crate::object::Object;
+use crate::scene::{read, Scene, SceneError};
+use crate::validation::{Issue, Severity};
+
+use std::path::{Path, PathBuf};
+
+/// PBRT is left handed like the renderer world but has y pointing up,
+/// turning half way around x puts it down
+fn to_world() -> Mat4f {
+    Mat4f::new(
+        1., 0., 0., 0., //
+        0., -1., 0., 0., //
+        0., 0., -1., 0., //
+        0., 0., 0., 1.,
+    )
+}
+
+/// Distant lights are placed this far away against their direction
+const SUN_DISTANCE: f32 = 1000.;
+
+fn direction(transform: &Mat4f, v: Vec3f) -> Vec3f {
+    transform.fixed_view::<3, 3>(0, 0) * v
+}
+
+fn matte(diffuse: Vec3f) -> Material {
+    Material {
+        ambient: Vec3f::repeat(0.1),
+        diffuse,
+        specular: Vec3f::zeros(),
+        shininess: 1.,
+        reflection: 0.,
+        refraction: 0.,
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    /// Directive name, the only unquoted word
+    Word(String),
+    Str(String),
+    Num(f32),
+    Open,
+    Close,
+}
+
+/// Splits the text into tokens with the line each one is on
+fn tokenize(file: &Path, text: &str) -> Result<Vec<(Token, usize)>, SceneError> {
+    let error = |line: usize, column: usize, message: String| SceneError::Parse {
+        file: file.to_path_buf(),
+        line,
+        column,
+        message,
+    };
+    let mut tokens = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let mut chars = line.char_indices().peekable();
+        while let Some((col, ch)) = chars.next() {
+            let token = match ch {
+                '#' => break,
+                '[' => Token::Open,
+                ']' => Token::Close,
+                '"' => {
+                    let mut s = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '"')) => break,
+                            Some((_, ch)) => s.push(ch),
+                            None => {
+                                return Err(error(
+                                    i + 1,
+                                    col + 1,
+                                    "unterminated string".to_string(),
+                                ))
+                            }
+                        }
+                    }
+                    Token::Str(s)
+                }
+                _ if ch.is_whitespace() => continue,
+                _ => {
+                    let mut word = ch.to_string();
+                    while let Some(&(_, ch)) = chars.peek() {
+                        if ch.is_whitespace() || "[]\"#".contains(ch) {
+                            break;
+                        }
+                        word.push(ch);
+                        chars.next();
+                    }
+                    if ch.is_alphabetic() {
+                        Token::Word(word)
+                    } else {
+                        let num = word.parse().map_err(|_| {
+                            error(i + 1, col + 1, format!("bad number \"{}\"", word))
+                        })?;
+                        Token::Num(num)
+                    }
+                }
+            };
+            tokens.push((token, i + 1));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone)]
+enum Arg {
+    Num(f32),
+    Str(String),
+    Nums(Vec<f32>),
+    Strs(Vec<String>),
+}
+
+struct Directive {
+    name: String,
+    line: usize,
+    args: Vec<Arg>,
+}
+
+impl Directive {
+    /// Positional string argument, like the type in `Shape "sphere"`
+    fn kind(&self) -> Option<&str> {
+        match self.args.first() {
+            Some(Arg::Str(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// All numbers of the arguments in a row, for `LookAt`, `Transform` and the like
+    fn numbers(&self) -> Vec<f32> {
+        let mut numbers = Vec::new();
+        for arg in &self.args {
+            match *arg {
+                Arg::Num(n) => numbers.push(n),
+                Arg::Nums(ref n) => numbers.extend(n),
+                _ => (),
+            }
+        }
+        numbers
+    }
+
+    /// Parameter list following `skip` positional arguments
+    fn params(&self, skip: usize) -> Params {
+        let mut params = Vec::new();
+        let mut args = self.args.iter().skip(skip);
+        while let (Some(Arg::Str(decl)), Some(value)) = (args.next(), args.next()) {
+            let mut words = decl.split_whitespace();
+            if let (Some(ty), Some(name)) = (words.next(), words.next()) {
+                params.push(Param {
+                    ty: ty.to_string(),
+                    name: name.to_string(),
+                    value: value.clone(),
+                });
+            }
+        }
+        Params(params)
+    }
+}
+
+fn directives(file: &Path, tokens: Vec<(Token, usize)>) -> Result<Vec<Directive>, SceneError> {
+    let error = |line: usize, message: &str| SceneError::Parse {
+        file: file.to_path_buf(),
+        line,
+        column: 1,
+        message: message.to_string(),
+    };
+    let mut directives: Vec<Directive> = Vec::new();
+    let mut tokens = tokens.into_iter();
+    while let Some((token, line)) = tokens.next() {
+        let arg = match token {
+            Token::Word(name) => {
+                directives.push(Directive {
+                    name,
+                    line,
+                    args: Vec::new(),
+                });
+                continue;
+            }
+            Token::Num(n) => Arg::Num(n),
+            Token::Str(s) => Arg::Str(s),
+            Token::Open => {
+                let (mut nums, mut strs) = (Vec::new(), Vec::new());
+                loop {
+                    match tokens.next() {
+                        Some((Token::Num(n), _)) => nums.push(n),
+                        Some((Token::Str(s), _)) => strs.push(s),
+                        Some((Token::Close, _)) => break,
+                        _ => return Err(error(line, "unterminated [")),
+                    }
+                }
+                match (nums.is_empty(), strs.is_empty()) {
+                    (_, true) => Arg::Nums(nums),
+                    (true, false) => Arg::Strs(strs),
+                    (false, false) => {
+                        return Err(error(line, "numbers and strings mixed in one array"))
+                    }
+                }
+            }
+            Token::Close => return Err(error(line, "unexpected ]")),
+        };
+        match directives.last_mut() {
+            Some(d) => d.args.push(arg),
+            None => return Err(error(line, "expected a directive")),
+        }
+    }
+    Ok(directives)
+}
+
+struct Param {
+    ty: String,
+    name: String,
+    value: Arg,
+}
+
+struct Params(Vec<Param>);
+
+impl Params {
+    fn get(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|p| p.name == name)
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    fn floats(&self, name: &str) -> Option<&[f32]> {
+        match self.get(name)?.value {
+            Arg::Num(ref n) => Some(std::slice::from_ref(n)),
+            Arg::Nums(ref n) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn float(&self, name: &str, default: f32) -> f32 {
+        self.floats(name)
+            .and_then(|f| f.first().cloned())
+            .unwrap_or(default)
+    }
+
+    fn point(&self, name: &str, default: Vec3f) -> Vec3f {
+        match self.floats(name) {
+            Some(&[x, y, z]) => Vec3f::new(x, y, z),
+            _ => default,
+        }
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name)?.value {
+            Arg::Str(ref s) => Some(s),
+            Arg::Strs(ref s) => s.first().map(String::as_str),
+            _ => None,
+        }
+    }
+}
+
+/// Attributes saved by `AttributeBegin`
+#[derive(Clone)]
+struct State {
+    transform: Mat4f,
+    material: String,
+    /// Emitted color of the shapes while inside an `AreaLightSource` block
+    area_light: Option<Vec3f>,
+}
+
+/// What the `Camera` directive asked for, the camera is built when the film size is known
+struct CameraSpec {
+    /// Camera space to PBRT world
+    transform: Mat4f,
+    kind: String,
+    params: Params,
+}
+
+struct Importer {
+    /// Directory relative paths are resolved against, the one of the main file
+    dir: PathBuf,
+    /// Name of the file being read, for warnings in included files
+    file: String,
+    /// Every included file, for reloading when one changes
+    includes: Vec<PathBuf>,
+    /// Files being read, the main one first
+    stack: Vec<PathBuf>,
+    state: State,
+    attributes: Vec<State>,
+    transforms: Vec<Mat4f>,
+    camera: Option<CameraSpec>,
+    film: (u32, u32),
+    /// Inside `ObjectBegin` and `ObjectEnd`, whose shapes are skipped
+    in_object: bool,
+    materials: MaterialLibrary,
+    objects: Vec<Object>,
+    lights: Vec<Light>,
+    warnings: Vec<Issue>,
+}
+
+impl Importer {
+    fn warn(&mut self, d: &Directive, field: &str, message: &str) {
+        let item = match d.kind() {
+            Some(kind) => format!("{} \"{}\"", d.name, kind),
+            None => d.name.clone(),
+        };
+        self.warnings.push(Issue {
+            severity: Severity::Warning,
+            item: format!("{}{}", self.file, item),
+            field: field.to_string(),
+            message: message.to_string(),
+            line: Some(d.line),
+        });
+    }
+
+    fn run(&mut self, path: &Path) -> Result<(), SceneError> {
+        let text = read(path)?;
+        let directives = directives(path, tokenize(path, &text)?)?;
+        for d in &directives {
+            self.directive(d)?;
+        }
+        Ok(())
+    }
+
+    /// RGB value of a spectrum parameter, others fall back to `default`
+    fn color(&mut self, d: &Directive, params: &Params, name: &str, default: Vec3f) -> Vec3f {
+        let param = match params.get(name) {
+            Some(p) => p,
+            None => return default,
+        };
+        match (param.ty.as_str(), params.floats(name)) {
+            ("rgb", Some(&[r, g, b])) | ("color", Some(&[r, g, b])) => Vec3f::new(r, g, b),
+            ("texture", _) => {
+                self.warn(d, name, "textures are not supported, ignored");
+                default
+            }
+            (ty, _) => {
+                self.warn(
+                    d,
+                    name,
+                    &format!("only rgb values are supported, {} ignored", ty),
+                );
+                default
+            }
+        }
+    }
+
+    fn material(&mut self, d: &Directive, kind: &str, params: &Params) -> Material {
+        let mut mat = matte(Vec3f::repeat(0.5));
+        match kind {
+            "matte" => mat.diffuse = self.color(d, params, "Kd", mat.diffuse),
+            "plastic" => {
+                mat.diffuse = self.color(d, params, "Kd", Vec3f::repeat(0.25));
+                mat.specular = self.color(d, params, "Ks", Vec3f::repeat(0.25));
+                mat.shininess = roughness_to_shininess(params.float("roughness", 0.1));
+            }
+            "glass" => {
+                let index = params.float("index", params.float("eta", 1.5));
+                mat = Material {
+                    ambient: Vec3f::zeros(),
+                    diffuse: Vec3f::zeros(),
+                    specular: Vec3f::repeat(1.),
+                    shininess: 1000.,
+                    // Refracted light only shows through reflective materials
+                    reflection: 0.1,
+                    refraction: index,
+                };
+            }
+            "mirror" => {
+                let kr = self.color(d, params, "Kr", Vec3f::repeat(0.9));
+                mat = Material {
+                    ambient: Vec3f::zeros(),
+                    diffuse: Vec3f::zeros(),
+                    specular: kr,
+                    shininess: 1000.,
+                    reflection: (kr.x + kr.y + kr.z) / 3.,
+                    refraction: 0.,
+                };
+            }
+            _ => {
+                mat.diffuse = self.color(d, params, "Kd", mat.diffuse);
+                self.warn(
+                    d,
+                    "type",
+                    "material type is not supported, imported as matte",
+                );
+            }
+        }
+        mat
+    }
+
+    /// Name of the current material in the library, the default one is added on first use
+    fn current_material(&mut self) -> MaterialRef {
+        if self.state.material == "default" && !self.materials.contains_key("default") {
+            self.materials
+                .insert("default".to_string(), matte(Vec3f::repeat(0.5)));
+        }
+        MaterialRef::Named(self.state.material.clone())
+    }
+
+    fn add_object(
+        &mut self,
+        d: &Directive,
+        shapes: Vec<Geometry>,
+        mesh: Option<String>,
+        transform: Mat4f,
+    ) {
+        let name = format!("{}{}", d.kind().unwrap_or("shape"), self.objects.len() + 1);
+        let material = self.current_material();
+        let mut obj = Object::new(&name, shapes, material, transform);
+        obj.mesh = mesh;
+        self.objects.push(obj);
+    }
+
+    /// Adds a light of the color given by the `field` intensity, the renderer
+    /// has no falloff so only the color is kept
+    fn add_light(&mut self, d: &Directive, field: &str, pos: Vec3f, color: Vec3f) {
+        let max = color.max();
+        let color = if max > 1. {
+            self.warn(
+                d,
+                field,
+                "intensity is not supported, the color is scaled to at most 1",
+            );
+            color / max
+        } else {
+            color
+        };
+        self.lights.push(Light {
+            pos,
+            color,
+            motion: None,
+        });
+    }
+
+    fn shape(&mut self, d: &Directive) -> Result<(), SceneError> {
+        if self.in_object {
+            self.warn(
+                d,
+                "ObjectBegin",
+                "object instancing is not supported, shape skipped",
+            );
+            return Ok(());
+        }
+        let params = d.params(1);
+        let world = to_world() * self.state.transform;
+        // World space points of the shape, area lights are put in their middle
+        let mut points = Vec::new();
+        match d.kind().unwrap_or("") {
+            "sphere" => {
+                if ["zmin", "zmax", "phimax"].iter().any(|p| params.has(p)) {
+                    self.warn(
+                        d,
+                        "phimax",
+                        "partial spheres are not supported, rendered whole",
+                    );
+                }
+                let scales: Vec<f32> = (0..3)
+                    .map(|i| direction(&world, Vec3f::ith(i, 1.)).norm())
+                    .collect();
+                if (scales[0] - scales[1]).abs() > 1e-3 || (scales[0] - scales[2]).abs() > 1e-3 {
+                    self.warn(
+                        d,
+                        "transform",
+                        "non-uniform scale is not supported for spheres",
+                    );
+                }
+                let radius = params.float("radius", 1.) * scales[0];
+                let center = transform_point(&world, Vec3f::zeros());
+                points.push(center);
+                if self.state.area_light.is_none() {
+                    let mut t = Mat4f::identity();
+                    set_translation(&mut t, center);
+                    self.add_object(d, vec![Geometry::Sphere(Sphere { radius })], None, t);
+                }
+            }
+            "trianglemesh" => {
+                let p = params.floats("P").unwrap_or(&[]).to_vec();
+                let vertices: Vec<Vec3f> = p
+                    .chunks(3)
+                    .filter(|c| c.len() == 3)
+                    .map(|c| Vec3f::new(c[0], c[1], c[2]))
+                    .collect();
+                let indices: Vec<usize> = match params.floats("indices") {
+                    Some(i) => i.iter().map(|&i| i as usize).collect(),
+                    None if vertices.len() == 3 => vec![0, 1, 2],
+                    None => {
+                        self.warn(d, "indices", "missing, shape skipped");
+                        return Ok(());
+                    }
+                };
+                if indices.iter().any(|&i| i >= vertices.len()) {
+                    self.warn(d, "indices", "vertex index out of range, shape skipped");
+                    return Ok(());
+                }
+                let mut shapes = Vec::new();
+                let mut degenerate = 0;
+                for t in indices.chunks(3).filter(|t| t.len() == 3) {
+                    let (a, b, c) = (vertices[t[0]], vertices[t[1]], vertices[t[2]]);
+                    if (b - a).cross(&(c - a)).norm() < 1e-8 {
+                        degenerate += 1;
+                    } else {
+                        shapes.push(Geometry::Triangle(Triangle {
+                            a,
+                            b,
+                            c,
+                            colors: None,
+                        }));
+                    }
+                }
+                if degenerate > 0 {
+                    self.warn(
+                        d,
+                        "indices",
+                        &format!("{} degenerate triangles left out", degenerate),
+                    );
+                }
+                points.extend(vertices.iter().map(|v| transform_point(&world, *v)));
+                if self.state.area_light.is_none() {
+                    self.add_object(d, shapes, None, world);
+                }
+            }
+            "plymesh" => {
+                let file = match params.string("filename") {
+                    Some(file) => file.to_string(),
+                    None => {
+                        self.warn(d, "filename", "missing, shape skipped");
+                        return Ok(());
+                    }
+                };
+                if self.state.area_light.is_some() {
+                    let mesh = mesh_import::load(&self.dir.join(&file))?;
+                    for t in &mesh.triangles {
+                        points.extend([t.a, t.b, t.c].iter().map(|v| transform_point(&world, *v)));
+                    }
+                } else {
+                    // Relative paths are resolved against the scene directory on load
+                    self.add_object(d, Vec::new(), Some(file), world);
+                }
+            }
+            _ => {
+                self.warn(d, "type", "shape type is not supported, skipped");
+                return Ok(());
+            }
+        }
+
+        if let Some(color) = self.state.area_light {
+            if !points.is_empty() {
+                let center =
+                    points.iter().fold(Vec3f::zeros(), |sum, p| sum + p) / points.len() as f32;
+                self.add_light(d, "L", center, color);
+            }
+            self.warn(
+                d,
+                "AreaLightSource",
+                "area lights are replaced by a point light in the middle of the shape",
+            );
+        }
+        Ok(())
+    }
+
+    fn light(&mut self, d: &Directive) {
+        let params = d.params(1);
+        let world = to_world() * self.state.transform;
+        let scale = self.color(d, &params, "scale", Vec3f::repeat(1.));
+        let from = transform_point(&world, params.point("from", Vec3f::zeros()));
+        match d.kind().unwrap_or("") {
+            "point" => {
+                let color = self.color(d, &params, "I", Vec3f::repeat(1.));
+                self.add_light(d, "I", from, color.component_mul(&scale));
+            }
+            "spot" => {
+                let color = self.color(d, &params, "I", Vec3f::repeat(1.));
+                self.warn(d, "type", "spot light imported as a point light");
+                self.add_light(d, "I", from, color.component_mul(&scale));
+            }
+            "distant" => {
+                let to = transform_point(&world, params.point("to", Vec3f::new(0., 0., 1.)));
+                let color = self.color(d, &params, "L", Vec3f::repeat(1.));
+                self.warn(
+                    d,
+                    "type",
+                    "distant light imported as a far away point light",
+                );
+                let pos = from - (to - from).normalize() * SUN_DISTANCE;
+                self.add_light(d, "L", pos, color.component_mul(&scale));
+            }
+            _ => self.warn(d, "type", "light type is not supported, skipped"),
+        }
+    }
+
+    fn camera(&mut self) -> Camera {
+        let (width, height) = self.film;
+        let spec = self.camera.take().unwrap_or(CameraSpec {
+            transform: Mat4f::identity(),
+            kind: "perspective".to_string(),
+            params: Params(Vec::new()),
+        });
+        let world = to_world() * spec.transform;
+        let eye = transform_point(&world, Vec3f::zeros());
+        let dir = direction(&world, Vec3f::new(0., 0., 1.)).normalize();
+        let up = direction(&world, Vec3f::new(0., 1., 0.)).normalize();
+        // PBRT angles and screen windows are for the shorter image side
+        let aspect = width as f32 / height as f32;
+        let vertical = aspect.max(1.);
+        let projection = if spec.kind == "orthographic" {
+            let height = match spec.params.floats("screenwindow") {
+                Some(&[_, _, bottom, top]) => top - bottom,
+                _ => 2. * vertical / aspect,
+            };
+            Projection::Orthographic { height }
+        } else {
+            Projection::Perspective
+        };
+        let half = spec.params.float("fov", 90.).to_radians() / 2.;
+        let fov = (half.tan() * vertical / aspect).atan().to_degrees();
+        let mut camera = CamBuilder::new()
+            .eye(eye)
+            .center(eye + dir)
+            .up(up)
+            .fov(fov)
+            .width(width)
+            .height(height)
+            .projection(projection)
+            .aperture(spec.params.float("lensradius", 0.))
+            .focal_distance(spec.params.float("focaldistance", 1e6))
+            .build();
+        camera.look_at(eye + dir);
+        camera
+    }
+
+    fn directive(&mut self, d: &Directive) -> Result<(), SceneError> {
+        let kind = d.kind().unwrap_or("").to_string();
+        match d.name.as_str() {
+            "WorldBegin" => self.state.transform = Mat4f::identity(),
+            "WorldEnd" => (),
+            "Identity" => self.state.transform = Mat4f::identity(),
+            "Translate" | "Scale" | "Rotate" | "LookAt" | "Transform" | "ConcatTransform" => {
+                let m = match transform(&d.name, &d.numbers()) {
+                    Some(m) => m,
+                    None => {
+                        self.warn(
+                            d,
+                            "arguments",
+                            "wrong number or degenerate arguments, ignored",
+                        );
+                        return Ok(());
+                    }
+                };
+                if d.name == "Transform" {
+                    self.state.transform = m;
+                } else {
+                    self.state.transform *= m;
+                }
+            }
+            "AttributeBegin" => self.attributes.push(self.state.clone()),
+            "AttributeEnd" => match self.attributes.pop() {
+                Some(state) => self.state = state,
+                None => self.warn(d, "AttributeBegin", "no matching AttributeBegin"),
+            },
+            "TransformBegin" => self.transforms.push(self.state.transform),
+            "TransformEnd" => match self.transforms.pop() {
+                Some(t) => self.state.transform = t,
+                None => self.warn(d, "TransformBegin", "no matching TransformBegin"),
+            },
+            "Camera" => {
+                if kind != "perspective" && kind != "orthographic" {
+                    self.warn(
+                        d,
+                        "type",
+                        "camera type is not supported, imported as perspective",
+                    );
+                }
+                let transform = match self.state.transform.try_inverse() {
+                    Some(t) => t,
+                    None => {
+                        self.warn(
+                            d,
+                            "transform",
+                            "camera transform can't be inverted, ignored",
+                        );
+                        Mat4f::identity()
+                    }
+                };
+                self.camera = Some(CameraSpec {
+                    transform,
+                    kind,
+                    params: d.params(1),
+                });
+            }
+            "Film" => {
+                let params = d.params(1);
+                let width = params.float("xresolution", 1280.) as u32;
+                let height = params.float("yresolution", 720.) as u32;
+                self.film = (width.max(1), height.max(1));
+            }
+            "Material" => {
+                let mut n = self.materials.len() + 1;
+                while self.materials.contains_key(&format!("material{}", n)) {
+                    n += 1;
+                }
+                let name = format!("material{}", n);
+                let mat = self.material(d, &kind, &d.params(1));
+                self.materials.insert(name.clone(), mat);
+                self.state.material = name;
+            }
+            "MakeNamedMaterial" => {
+                let params = d.params(1);
+                let ty = params.string("type").unwrap_or("matte").to_string();
+                let mat = self.material(d, &ty, &params);
+                self.materials.insert(kind, mat);
+            }
+            "NamedMaterial" => {
+                if self.materials.contains_key(&kind) {
+                    self.state.material = kind;
+                } else {
+                    self.warn(d, "name", "unknown material, ignored");
+                }
+            }
+            "Shape" => self.shape(d)?,
+            "LightSource" => self.light(d),
+            "AreaLightSource" => {
+                let params = d.params(1);
+                let color = self.color(d, &params, "L", Vec3f::repeat(1.));
+                let scale = self.color(d, &params, "scale", Vec3f::repeat(1.));
+                self.state.area_light = Some(color.component_mul(&scale));
+            }
+            "Include" => {
+                let path = self.dir.join(&kind);
+                if self.stack.contains(&path) {
+                    return Err(SceneError::IncludeCycle { file: path });
+                }
+                let file = std::mem::replace(&mut self.file, format!("{}: ", kind));
+                self.includes.push(path.clone());
+                self.stack.push(path.clone());
+                self.run(&path)?;
+                self.stack.pop();
+                self.file = file;
+            }
+            "ObjectBegin" => {
+                self.in_object = true;
+                self.warn(
+                    d,
+                    "ObjectBegin",
+                    "object instancing is not supported, skipped",
+                );
+            }
+            "ObjectEnd" => self.in_object = false,
+            "Sampler" | "PixelFilter" | "Integrator" | "Accelerator" => self.warn(
+                d,
+                "type",
+                "render settings are not imported, the renderer uses its own",
+            ),
+            _ => self.warn(d, "directive", "not supported, ignored"),
+        }
+        Ok(())
+    }
+}
+
+/// Matrix of a transform directive, `None` for wrong arguments
+fn transform(name: &str, n: &[f32]) -> Option<Mat4f> {
+    match (name, n) {
+        ("Translate", &[x, y, z]) => Some(Mat4f::new_translation(&Vec3f::new(x, y, z))),
+        ("Scale", &[x, y, z]) => Some(Mat4f::new_nonuniform_scaling(&Vec3f::new(x, y, z))),
+        ("Rotate", &[angle, x, y, z]) => {
+            let axis = Unit::try_new(Vec3f::new(x, y, z), 1e-12)?;
+            Some(Rotation3::from_axis_angle(&axis, angle.to_radians()).to_homogeneous())
+        }
+        ("LookAt", &[ex, ey, ez, lx, ly, lz, ux, uy, uz]) => {
+            let eye = Vec3f::new(ex, ey, ez);
+            let dir = (Vec3f::new(lx, ly, lz) - eye).try_normalize(1e-12)?;
+            let right = Vec3f::new(ux, uy, uz)
+                .try_normalize(1e-12)?
+                .cross(&dir)
+                .try_normalize(1e-12)?;
+            let up = dir.cross(&right);
+            let mut camera_to_world = Mat4f::identity();
+            camera_to_world
+                .fixed_view_mut::<3, 1>(0, 0)
+                .copy_from(&right);
+            camera_to_world.fixed_view_mut::<3, 1>(0, 1).copy_from(&up);
+            camera_to_world.fixed_view_mut::<3, 1>(0, 2).copy_from(&dir);
+            camera_to_world.fixed_view_mut::<3, 1>(0, 3).copy_from(&eye);
+            camera_to_world.try_inverse()
+        }
+        // Matrices are written column by column
+        ("Transform", n) | ("ConcatTransform", n) if n.len() == 16 => {
+            Some(Mat4f::from_column_slice(n))
+        }
+        _ => None,
+    }
+}
+
+/// Reads a `.pbrt` file into a scene, warnings list what couldn't be imported
+pub fn import(path: &Path) -> Result<(Scene, Vec<Issue>), SceneError> {
+    let mut importer = Importer {
+        dir: path.parent().unwrap_or_else(|| Path::new("")).to_path_buf(),
+        file: String::new(),
+        includes: Vec::new(),
+        stack: vec![path.to_path_buf()],
+        state: State {
+            transform: Mat4f::identity(),
+            material: "default".to_string(),
+            area_light: None,
+        },
+        attributes: Vec::new(),
+        transforms: Vec::new(),
+        camera: None,
+        film: (1280, 720),
+        in_object: false,
+        materials: MaterialLibrary::new(),
+        objects: Vec::new(),
+        lights: Vec::new(),
+        warnings: Vec::new(),
+    };
+    importer.run(path)?;
+
+    let cam = importer.camera();
+    let mut scene = Scene::new(cam, importer.objects, importer.lights);
+    scene.materials = importer.materials;
+    scene.sources = importer.includes;
+    Ok((scene, importer.warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<Directive> {
+        let file = Path::new("test.pbrt");
+        directives(file, tokenize(file, text).unwrap()).unwrap()
+    }
+
+    fn error_line(result: Result<Vec<Directive>, SceneError>) -> usize {
+        match result {
+            Err(SceneError::Parse { line, .. }) => line,
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn tokens() {
+        let text = "Shape \"sphere\" # comment \"ignored\"\n  \"float radius\" [2.5 -1e2]";
+        let tokens = tokenize(Path::new("test.pbrt"), text).unwrap();
+        let expected = vec![
+            (Token::Word("Shape".to_string()), 1),
+            (Token::Str("sphere".to_string()), 1),
+            (Token::Str("float radius".to_string()), 2),
+            (Token::Open, 2),
+            (Token::Num(2.5), 2),
+            (Token::Num(-100.), 2),
+            (Token::Close, 2),
+        ];
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn token_errors() {
+        let file = Path::new("test.pbrt");
+        assert!(matches!(
+            tokenize(file, "Shape\n\"sphere"),
+            Err(SceneError::Parse {
+                line: 2,
+                column: 1,
+                ..
+            })
+        ));
+        assert!(matches!(
+            tokenize(file, "Translate 1 2x 3"),
+            Err(SceneError::Parse {
+                line: 1,
+                column: 13,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn directive_arguments() {
+        let d = parse(
+            "LookAt 0 0 5  0 0 0  0 1 0\n\
+             Shape \"sphere\" \"float radius\" 2\n\
+             Texture \"t\" \"spectrum\" \"imagemap\" \"string filename\" [\"a.png\"]",
+        );
+        assert_eq!(d.len(), 3);
+        assert_eq!(d[0].name, "LookAt");
+        assert_eq!(d[0].numbers(), vec![0., 0., 5., 0., 0., 0., 0., 1., 0.]);
+        assert_eq!(d[1].kind(), Some("sphere"));
+        assert_eq!(d[1].line, 2);
+        assert_eq!(d[1].params(1).float("radius", 1.), 2.);
+        assert_eq!(d[2].params(3).string("filename"), Some("a.png"));
+    }
+
+    #[test]
+    fn directive_errors() {
+        let file = Path::new("test.pbrt");
+        let run = |text| directives(file, tokenize(file, text).unwrap());
+        assert_eq!(error_line(run("1 2 3")), 1);
+        assert_eq!(error_line(run("Translate\n[1 2 3")), 2);
+        assert_eq!(error_line(run("Foo [1 \"a\"]")), 1);
+        assert_eq!(error_line(run("Foo ]")), 1);
+    }
+
+    #[test]
+    fn transforms() {
+        let p = Vec3f::new(1., 2., 3.);
+        let moved = transform("Translate", &[1., 0., -1.]).unwrap();
+        assert_eq!(transform_point(&moved, p), Vec3f::new(2., 2., 2.));
+
+        let turned = transform("Rotate", &[90., 0., 0., 1.]).unwrap();
+        assert!(
+            (transform_point(&turned, Vec3f::new(1., 0., 0.)) - Vec3f::new(0., 1., 0.)).norm()
+                < 1e-6
+        );
+
+        // Column major like in the file, the translation is in the last four numbers
+        let mut n = [0.; 16];
+        n[0] = 1.;
+        n[5] = 1.;
+        n[10] = 1.;
+        n[12..].copy_from_slice(&[4., 5., 6., 1.]);
+        let matrix = transform("Transform", &n).unwrap();
+        assert_eq!(transform_point(&matrix, p), Vec3f::new(5., 7., 9.));
+
+        // World to camera, the eye ends up at the origin looking along +z
+        let look = transform("LookAt", &[0., 0., 5., 0., 0., 0., 0., 1., 0.]).unwrap();
+        assert!(transform_point(&look, Vec3f::new(0., 0., 5.)).norm() < 1e-6);
+        assert!(
+            (transform_point(&look, Vec3f::new(0., 0., 0.)) - Vec3f::new(0., 0., 5.)).norm() < 1e-6
+        );
+
+        assert!(transform("Translate", &[1., 2.]).is_none());
+        assert!(transform("Rotate", &[90., 0., 0., 0.]).is_none());
+        assert!(transform("LookAt", &[0., 0., 0., 0., 0., 0., 0., 1., 0.]).is_none());
+    }
+}
@@ -1,84 +1,349 @@
-use crate::camera::Camera;
+use crate::camera::{Camera, FisheyeMapping, Projection, StereoLayout, StereoMethod};
 use crate::geometry::{Geometry,Shape};
 use crate::light::Light;
 use crate::object::Object;
-use crate::surface::{Division, Surface};
+use crate::surface::Division;
 use crate::material::Hit;
-use crate::math::{Vec3f, Mat4f};
+use crate::math::{Vec3f, Mat4f, translation, transform_point};
+use crate::render_settings::{Background, RenderSettings};
+use crate::sampling;
 use crate::scene::Scene;
 
-use num_cpus;
-use scoped_threadpool::Pool;
-
 use std::f32::INFINITY;
+use std::f32::consts::{FRAC_PI_2, PI};
 
 pub struct Ray {
     pub dir: Vec3f,
     pub origin: Vec3f,
+
+    /// Moment within the frame the ray is traced at,
+    /// 0 is the previous frame and 1 is the current one
+    pub time: f32,
+}
+
+/// Which eye a primary ray is traced for
+#[derive(Clone, Copy)]
+enum Eye {
+    Center,
+    Left,
+    Right,
+}
+
+impl Eye {
+    /// Side of the eye along the camera right vector
+    fn side(self) -> f32 {
+        match self {
+            Eye::Center => 0.,
+            Eye::Left => -1.,
+            Eye::Right => 1.,
+        }
+    }
 }
 
 struct Params {
-    max_depth: u8,
+    settings: RenderSettings,
+    /// Distance secondary rays start off the surface
+    ray_offset: f32,
+    /// Size of a single eye view, the whole frame for mono rendering
+    view_width: u32,
+    view_height: u32,
     aspect: f32,
     angle: f32,
     inv_width: f32,
     inv_height: f32,
-    right: Vec3f,
+    focal_distance: f32,
+}
+
+/// Everything the tiles of one frame share
+pub struct Frame<'a> {
+    cam: &'a Camera,
+    objects: &'a [Object],
+    lights: &'a [Light],
+    params: Params,
+}
+
+impl<'a> Frame<'a> {
+    pub fn new(scene: &'a Scene) -> Frame<'a> {
+        let cam = &scene.cam;
+        let (view_width, view_height) = match cam.stereo {
+            None => (cam.width, cam.height),
+            Some(ref stereo) => match stereo.layout {
+                StereoLayout::SideBySide => (cam.width / 2, cam.height),
+                StereoLayout::TopBottom => (cam.width, cam.height / 2),
+            },
+        };
+        let params = Params {
+            settings: scene.settings.clone(),
+            ray_offset: scene
+                .settings
+                .ray_offset
+                .unwrap_or_else(|| scene_size(scene) * RELATIVE_RAY_OFFSET),
+            view_width,
+            view_height,
+            aspect: view_width as f32 / view_height as f32,
+            angle: cam.fov.to_radians().tan(),
+            inv_width: 1. / view_width as f32,
+            inv_height: 1. / view_height as f32,
+            focal_distance: if cam.autofocus {
+                autofocus(cam, &scene.objects)
+            } else {
+                cam.focal_distance
+            },
+        };
+        Frame {
+            cam,
+            objects: &scene.objects,
+            lights: &scene.lights,
+            params,
+        }
+    }
+
+    pub fn render_tile(&self, chunk: &Division) {
+        process_part(self.cam, self.objects, self.lights, chunk, &self.params);
+    }
 }
 
-pub fn march(scene: &Scene) -> Vec<u8> {
-    let objects = &scene.objects;
-    let cam = &scene.cam;
-    let lights = &scene.lights;
-    let mut pool = Pool::new(num_cpus::get() as u32);
-    let surf = Surface::new(cam.width, cam.height);
-
-    let params = Params {
-        max_depth: 5,
-        aspect: cam.width as f32 / cam.height as f32,
-        angle: cam.fov.to_radians().tan(),
-        inv_width: 1. / cam.width as f32,
-        inv_height: 1. / cam.height as f32,
-        right: cam.up.cross(&cam.dir).normalize(),
+/// Ray offset for a scene of unit size, gives the old fixed 0.001 for the usual 100 units
+const RELATIVE_RAY_OFFSET: f32 = 1e-5;
+
+/// Diagonal of the box around the camera and the objects
+fn scene_size(scene: &Scene) -> f32 {
+    let mut min = scene.cam.eye;
+    let mut max = scene.cam.eye;
+    let mut add = |p: Vec3f| {
+        min = min.inf(&p);
+        max = max.sup(&p);
     };
-    pool.scoped(|scope| {
-        for chunk in surf.divide(32, 32) {
-            scope.execute(|| {
-                process_part(cam, objects, lights, chunk, &params);
-            });
+    for obj in &scene.objects {
+        let origin = translation(&obj.world);
+        add(origin);
+        for shape in &obj.shapes {
+            match *shape {
+                Geometry::Sphere(ref s) => {
+                    let radius = s.scaled_radius(&obj.world);
+                    add(origin - Vec3f::new(radius, radius, radius));
+                    add(origin + Vec3f::new(radius, radius, radius));
+                }
+                Geometry::Cuboid(ref b) => {
+                    for corner in 0..8 {
+                        let pick = |bit: usize, e: f32| if corner & bit == 0 { 0. } else { e };
+                        let local = Vec3f::new(
+                            pick(1, b.extent.x),
+                            pick(2, b.extent.y),
+                            pick(4, b.extent.z),
+                        );
+                        add(transform_point(&obj.world, local));
+                    }
+                }
+                Geometry::Triangle(ref t) => {
+                    let (a, b, c) = t.corners(&obj.world);
+                    add(a);
+                    add(b);
+                    add(c);
+                }
+            }
         }
-    });
-    surf.pixels
+    }
+    (max - min).norm()
+}
+
+/// Distance along the view direction to the object under the screen center,
+/// falls back to the configured focal distance when nothing is hit
+fn autofocus(cam: &Camera, objects: &[Object]) -> f32 {
+    let dir = cam.dir.normalize();
+    let ray = Ray {
+        origin: cam.eye,
+        dir,
+        time: 1.,
+    };
+    match nearest_hit(&ray, objects) {
+        Some(i) => i.tnear,
+        None => cam.focal_distance,
+    }
 }
 
-fn process_part(cam: &Camera, objects: &[Object], lights: &[Light], chunk: Division, params: &Params) {
-    let aspect = params.aspect;
-    let angle = params.angle;
-    let inv_width = params.inv_width;
-    let inv_height = params.inv_height;
-    let right = params.right;
+/// Maps a frame pixel to the eye it belongs to and the pixel within that eye's view
+fn eye_pixel(cam: &Camera, params: &Params, x: u32, y: u32) -> (Eye, u32, u32) {
+    match cam.stereo {
+        None => (Eye::Center, x, y),
+        Some(ref stereo) => match stereo.layout {
+            StereoLayout::SideBySide if x < params.view_width => (Eye::Left, x, y),
+            StereoLayout::SideBySide => (Eye::Right, x - params.view_width, y),
+            StereoLayout::TopBottom if y < params.view_height => (Eye::Left, x, y),
+            StereoLayout::TopBottom => (Eye::Right, x, y - params.view_height),
+        },
+    }
+}
+
+/// Maps the point (`nx`, `ny`) of the normalized image plane onto a ray origin
+/// and direction for a camera placed at `eye` looking along `view`.
+/// Returns `None` for points outside of the projected image.
+fn project(
+    cam: &Camera,
+    params: &Params,
+    eye: Vec3f,
+    view: Vec3f,
+    right: Vec3f,
+    nx: f32,
+    ny: f32,
+) -> Option<(Vec3f, Vec3f)> {
+    let up = cam.up;
+
+    // Direction for the given longitude around the up axis and latitude above the horizon
+    let spherical =
+        |lon: f32, lat: f32| (view * lon.cos() + right * lon.sin()) * lat.cos() + up * lat.sin();
+
+    match cam.projection {
+        Projection::Perspective => {
+            let xx = right * nx * params.angle * params.aspect;
+            let yy = up * ny * params.angle;
+            Some((eye, (view + xx + yy).normalize()))
+        }
+        Projection::Orthographic { height } => {
+            let half = height / 2.;
+            let origin = eye + right * nx * half * params.aspect + up * ny * half;
+            Some((origin, view))
+        }
+        Projection::Fisheye { mapping } => {
+            let (px, py) = (nx * params.aspect, ny);
+            let r = (px * px + py * py).sqrt();
+            if r > 1. {
+                return None;
+            }
+            let fov = cam.fov.to_radians();
+            let theta = match mapping {
+                FisheyeMapping::Equidistant => r * fov,
+                FisheyeMapping::Equisolid => 2. * (r * (fov / 2.).sin()).asin(),
+            };
+            let radial = if r > 0. {
+                (right * px + up * py) / r
+            } else {
+                Vec3f::new(0., 0., 0.)
+            };
+            Some((eye, (view * theta.cos() + radial * theta.sin()).normalize()))
+        }
+        Projection::Cylindrical => {
+            let lon = nx * PI;
+            let lat = (ny * params.angle).atan();
+            Some((eye, spherical(lon, lat).normalize()))
+        }
+        Projection::Equirectangular => Some((eye, spherical(nx * PI, ny * FRAC_PI_2).normalize())),
+    }
+}
 
-    let default_color: Vec3f = Vec3f::new(
-        chunk.x0 as f32 / cam.width as f32,
-        chunk.y0 as f32 / cam.height as f32,
-        0.7,
+/// Builds a primary ray through the point (`x`, `y`) of the eye view,
+/// starting at the point (`lens_u`, `lens_v`) of the unit square mapped onto the aperture.
+/// The camera is expected to be already moved to the moment the ray is traced at.
+/// Returns `None` for points outside of the projected image.
+fn primary_ray(
+    cam: &Camera,
+    params: &Params,
+    eye: Eye,
+    x: f32,
+    y: f32,
+    lens_u: f32,
+    lens_v: f32,
+) -> Option<(Vec3f, Vec3f)> {
+    let nx = 2. * x * params.inv_width - 1.;
+    let ny = 1. - 2. * y * params.inv_height;
+    let planar = matches!(
+        cam.projection,
+        Projection::Perspective | Projection::Orthographic { .. }
     );
 
+    let mut view = cam.dir.normalize();
+    let mut right = cam.up.cross(&cam.dir).normalize();
+    let (origin, dir) = match cam.stereo {
+        Some(ref stereo) if planar => {
+            let offset = right * eye.side() * stereo.interocular / 2.;
+            let converge = cam.eye + view * stereo.convergence;
+            match stereo.method {
+                StereoMethod::ToeIn => {
+                    view = (converge - (cam.eye + offset)).normalize();
+                    right = cam.up.cross(&view).normalize();
+                    project(cam, params, cam.eye + offset, view, right, nx, ny)?
+                }
+                StereoMethod::OffAxis => {
+                    // Shift the eye but keep the image plane where the mono view has it at the convergence distance
+                    let (origin, dir) = project(cam, params, cam.eye, view, right, nx, ny)?;
+                    let target = origin + dir * (stereo.convergence / dir.dot(&view));
+                    (origin + offset, (target - origin - offset).normalize())
+                }
+            }
+        }
+        Some(ref stereo) => {
+            // Omni-directional stereo: the eyes turn around the up axis together with the ray
+            let (origin, dir) = project(cam, params, cam.eye, view, right, nx, ny)?;
+            let horizontal = dir - cam.up * dir.dot(&cam.up);
+            if horizontal.norm() < 1e-6 {
+                (origin, dir)
+            } else {
+                let side = cam.up.cross(&horizontal).normalize();
+                let eye_origin = origin + side * eye.side() * stereo.interocular / 2.;
+                let target = origin + dir * stereo.convergence;
+                (eye_origin, (target - eye_origin).normalize())
+            }
+        }
+        None => project(cam, params, cam.eye, view, right, nx, ny)?,
+    };
+
+    // Thin lens only makes sense for planar image projections
+    if cam.aperture <= 0. || !planar {
+        return Some((origin, dir));
+    }
+
+    // Every ray through the lens converges at the focal plane
+    let focus = origin + dir * (params.focal_distance / dir.dot(&view));
+    let (u, v) = cam.bokeh.sample(lens_u, lens_v);
+    let origin = origin + (right * u + cam.up * v) * cam.aperture;
+    Some((origin, (focus - origin).normalize()))
+}
+
+fn process_part(
+    cam: &Camera,
+    objects: &[Object],
+    lights: &[Light],
+    chunk: &Division,
+    params: &Params,
+) {
+    let samples = cam.samples.max(1);
+    let tracer = Tracer {
+        objects,
+        lights,
+        params,
+        tile: Vec3f::new(
+            chunk.x0 as f32 / cam.width as f32,
+            chunk.y0 as f32 / cam.height as f32,
+            0.7,
+        ),
+    };
+
     for yi in 0..chunk.h {
         for xi in 0..chunk.w {
             let x = xi + chunk.x0;
             let y = yi + chunk.y0;
+            let (eye, ex, ey) = eye_pixel(cam, params, x, y);
 
-            let xx = right * (2. * (x as f32 + 0.5) * inv_width - 1.) * angle * aspect;
-            let yy = cam.up * (1. - 2. * (y as f32 + 0.5) * inv_height) * angle;
-
-            let ray = Ray {
-                origin: cam.eye,
-                dir: (cam.dir + xx + yy).normalize(),
-            };
-
-            let color = trace(&ray, objects, lights, &default_color, 0, params.max_depth);
-            chunk.set_color(x, y, color);
+            let mut color = Vec3f::new(0., 0., 0.);
+            for s in 0..samples {
+                let (lens_u, lens_v) = sampling::pixel_sample(s, x, y);
+                let time = cam.shutter_time(sampling::time_sample(s, x, y));
+                let cam_t = cam.at(time);
+                if let Some((origin, dir)) = primary_ray(
+                    &cam_t,
+                    params,
+                    eye,
+                    ex as f32 + 0.5,
+                    ey as f32 + 0.5,
+                    lens_u,
+                    lens_v,
+                ) {
+                    let ray = Ray { origin, dir, time };
+                    color +=
+                        tracer.trace(&ray, params.settings.background.primary, Depth::default());
+                }
+            }
+            chunk.set_color(x, y, color / samples as f32);
         }
     }
 }
@@ -92,107 +357,165 @@ fn hit(ray: &Ray, transform: &Mat4f, shape: &Geometry) -> (f32, f32) {
     }
 }
 
-fn trace(
-    ray: &Ray,
-    objects: &[Object],
-    lights: &[Light],
-    default_color: &Vec3f,
-    depth: u8,
-    max_depth: u8,
-) -> Vec3f {
-    if depth > max_depth {
-        return Vec3f::new(0.5, 0.5, 0.5);
-    }
-    let (mut tnear, mut tfar) = (INFINITY, INFINITY);
-    let mut hit_obj = &objects[0];
-    let mut hit_shape = &hit_obj.shapes[0];
+struct Intersection<'a> {
+    tnear: f32,
+    tfar: f32,
+    obj: &'a Object,
+    shape: &'a Geometry,
+    /// Object transform at the moment of the ray
+    transform: Mat4f,
+}
+
+fn nearest_hit<'a>(ray: &Ray, objects: &'a [Object]) -> Option<Intersection<'a>> {
+    let mut nearest: Option<Intersection> = None;
     for obj in objects {
+        let transform = obj.transform_at(ray.time);
         for shape in obj.shapes.iter() {
-            let (t1, t2) = hit(ray, &obj.transform, shape);
-            if t1 < tnear {
-                tnear = t1;
-                tfar = t2;
-                hit_shape = shape;
-                hit_obj = &obj;
+            let (t1, t2) = hit(ray, &transform, shape);
+            if t1 < nearest.as_ref().map_or(INFINITY, |i| i.tnear) {
+                nearest = Some(Intersection {
+                    tnear: t1,
+                    tfar: t2,
+                    obj,
+                    shape,
+                    transform,
+                });
             }
         }
     }
-    let mut color = *default_color;
-    if tnear != INFINITY {
-        color = Vec3f::new(0., 0., 0.);
-        let nhit = hit_shape.get_normal(&hit_obj.transform, ray, tnear);
+    nearest
+}
+
+/// Scene and settings every traced ray needs
+struct Tracer<'a> {
+    objects: &'a [Object],
+    lights: &'a [Light],
+    params: &'a Params,
+    /// Color of the render tile, seen where the background is `Tiles`
+    tile: Vec3f,
+}
+
+/// Reflections and refractions on the way to a hit
+#[derive(Clone, Copy, Default)]
+struct Depth {
+    reflection: u8,
+    refraction: u8,
+}
+
+impl Depth {
+    fn total(self) -> u16 {
+        u16::from(self.reflection) + u16::from(self.refraction)
+    }
+}
+
+impl<'a> Tracer<'a> {
+    fn background(&self, background: Background) -> Vec3f {
+        match background {
+            Background::Tiles => self.tile,
+            Background::Color(color) => color,
+        }
+    }
+
+    /// True if something between `phit` and `light` shades it
+    fn shaded(&self, phit: Vec3f, nhit: Vec3f, light: &Light, time: f32) -> bool {
+        // Only things between the point and the light cast a shadow on it
+        let light_distance = (light.pos - phit).norm();
+        let shadow_ray = Ray {
+            origin: phit + nhit * self.params.ray_offset,
+            dir: (light.pos - phit).normalize(),
+            time,
+        };
+        self.objects.iter().any(|obj| {
+            let obj_transform = obj.transform_at(time);
+            obj.shapes
+                .iter()
+                .any(|shape| hit(&shadow_ray, &obj_transform, shape).0 < light_distance)
+        })
+    }
+
+    fn trace(&self, ray: &Ray, background: Background, depth: Depth) -> Vec3f {
+        let Intersection {
+            tnear,
+            tfar,
+            obj: hit_obj,
+            shape: hit_shape,
+            transform,
+        } = match nearest_hit(ray, self.objects) {
+            Some(intersection) => intersection,
+            None => return self.background(background),
+        };
+        let settings = &self.params.settings;
+        let offset = self.params.ray_offset;
+        let nhit = hit_shape.get_normal(&transform, ray, tnear);
         let phit = ray.origin + ray.dir * tnear;
-        for light in lights {
-            let mut light_shaded = false;
-            'shade: for obj in objects {
-                for shape in obj.shapes.iter() {
-                    let shadow_ray = Ray {
-                        origin: phit + nhit * 0.001,
-                        dir: (light.pos - phit).normalize(),
-                    };
-                    let (t1, _) = hit(&shadow_ray, &obj.transform, shape);
-                    if t1 != INFINITY {
-                        light_shaded = true;
-                        break 'shade;
-                    }
+        let mat = match hit_shape.color_at(&transform, phit) {
+            Some(tint) => hit_obj.mat.tinted(tint),
+            None => hit_obj.mat,
+        };
+
+        let mut direct = Vec3f::new(0., 0., 0.);
+        if depth.total() <= u16::from(settings.max_diffuse_depth) {
+            let h = Hit { ray, tnear, nhit };
+            for light in self.lights {
+                if !self.shaded(phit, nhit, light, ray.time) {
+                    direct += mat.direct(&h, light);
                 }
             }
-            let reflected_color = if hit_obj.mat.reflection > 0.0 {
-                let reflection_ray = Ray {
-                    origin: phit + nhit * 0.001,
-                    dir: ray.dir - 2. * nhit * nhit.dot(&ray.dir),
-                };
-                trace(
-                    &reflection_ray,
-                    objects,
-                    lights,
-                    &Vec3f::new(0.0, 0.2, 0.4),
-                    depth + 1,
-                    max_depth
-                )
-            } else {
-                Vec3f::new(0., 0., 0.)
+        }
+
+        // Reflected and refracted light only matters for reflective materials
+        if mat.reflection <= 0.0 {
+            return mat.compute_color(direct, Vec3f::new(0., 0., 0.), Vec3f::new(0., 0., 0.));
+        }
+        let exhausted = settings.background.exhausted;
+
+        let deeper = depth.total() < u16::from(settings.max_depth);
+        let reflected_color = if deeper && depth.reflection < settings.max_reflection_depth {
+            let reflection_ray = Ray {
+                origin: phit + nhit * offset,
+                dir: ray.dir - 2. * nhit * nhit.dot(&ray.dir),
+                time: ray.time,
+            };
+            let depth = Depth {
+                reflection: depth.reflection + 1,
+                ..depth
             };
+            self.trace(&reflection_ray, settings.background.reflection, depth)
+        } else {
+            exhausted
+        };
 
-            let refracted_color = if hit_obj.mat.refraction > 0.0 {
-                // Snell's law
-                let n2 = hit_obj.mat.refraction;
-
-                let dot_in = nhit.dot(&ray.dir);
-                let factor_in = (n2 * n2 / (dot_in * dot_in) + 1.).sqrt() - 1.;
-                let ray_in = Ray {
-                    origin: phit + nhit * 0.001,
-                    dir: ray.dir + nhit * dot_in * factor_in,
-                };
-                let (_, tfar_in) = hit(&ray_in, &hit_obj.transform, &hit_shape);
-                let nhit_in = hit_shape.get_normal(&hit_obj.transform, &ray_in, tfar);
-
-                let dot_out = nhit_in.dot(&ray_in.dir);
-                let factor_out = (-n2 * n2 / (dot_out * dot_out) + 1.).sqrt() - 1.;
-                let refraction_ray = Ray {
-                    origin: ray_in.origin + ray_in.dir * tfar_in - nhit_in * 0.001,
-                    dir: ray_in.dir + nhit_in * dot_out * factor_out,
-                };
-                trace(
-                    &refraction_ray,
-                    objects,
-                    lights,
-                    &Vec3f::new(0.0, 0.2, 0.4),
-                    depth + 1,
-                    max_depth,
-                )
-            } else {
-                Vec3f::new(0., 0., 0.)
+        let refracted_color = if mat.refraction <= 0.0 {
+            Vec3f::new(0., 0., 0.)
+        } else if deeper && depth.refraction < settings.max_refraction_depth {
+            // Snell's law
+            let n2 = mat.refraction;
+
+            let dot_in = nhit.dot(&ray.dir);
+            let factor_in = (n2 * n2 / (dot_in * dot_in) + 1.).sqrt() - 1.;
+            let ray_in = Ray {
+                origin: phit + nhit * offset,
+                dir: ray.dir + nhit * dot_in * factor_in,
+                time: ray.time,
             };
-            let h: Hit = Hit { ray, tnear, nhit };
-            color += hit_obj.mat.compute_color(
-                &h,
-                light,
-                reflected_color,
-                refracted_color,
-                light_shaded,
-            );
-        }
+            let (_, tfar_in) = hit(&ray_in, &transform, hit_shape);
+            let nhit_in = hit_shape.get_normal(&transform, &ray_in, tfar);
+
+            let dot_out = nhit_in.dot(&ray_in.dir);
+            let factor_out = (-n2 * n2 / (dot_out * dot_out) + 1.).sqrt() - 1.;
+            let refraction_ray = Ray {
+                origin: ray_in.origin + ray_in.dir * tfar_in - nhit_in * offset,
+                dir: ray_in.dir + nhit_in * dot_out * factor_out,
+                time: ray.time,
+            };
+            let depth = Depth {
+                refraction: depth.refraction + 1,
+                ..depth
+            };
+            self.trace(&refraction_ray, settings.background.refraction, depth)
+        } else {
+            exhausted
+        };
+        mat.compute_color(direct, reflected_color, refracted_color)
     }
-    color
 }
@@ -0,0 +1,98 @@
+//! Ray tracer settings kept in the scene, some can be overridden from the command line
+
+use crate::math::Vec3f;
+
+use serde::{Deserialize, Serialize};
+
+/// What a ray which hits nothing sees
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum Background {
+    /// Gradient over the render tiles, shows how the frame was divided
+    Tiles,
+    Color(Vec3f),
+}
+
+/// Background for every kind of ray
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Backgrounds {
+    /// Rays from the camera
+    pub primary: Background,
+    pub reflection: Background,
+    pub refraction: Background,
+    /// Color of reflected and refracted rays cut off by the depth limits
+    pub exhausted: Vec3f,
+}
+
+impl Default for Backgrounds {
+    fn default() -> Backgrounds {
+        Backgrounds {
+            primary: Background::Tiles,
+            reflection: Background::Color(Vec3f::new(0.0, 0.2, 0.4)),
+            refraction: Background::Color(Vec3f::new(0.0, 0.2, 0.4)),
+            exhausted: Vec3f::new(0.5, 0.5, 0.5),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    /// Reflections and refractions together followed along a path, every
+    /// hit on glass spawns both so this keeps the ray count down
+    pub max_depth: u8,
+    /// Reflections followed along a path before `exhausted` is used
+    pub max_reflection_depth: u8,
+    /// Refractions followed along a path before `exhausted` is used
+    pub max_refraction_depth: u8,
+    /// Reflections and refractions after which hit points still get direct
+    /// light, deeper ones only get ambient light and cast no shadow rays
+    pub max_diffuse_depth: u8,
+    /// Distance secondary rays start off the surface to not hit it again,
+    /// scaled with the size of the scene when not given
+    pub ray_offset: Option<f32>,
+    pub background: Backgrounds,
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            max_depth: 5,
+            max_reflection_depth: 5,
+            max_refraction_depth: 5,
+            max_diffuse_depth: 10,
+            ray_offset: None,
+            background: Backgrounds::default(),
+        }
+    }
+}
+
+/// Settings given on the command line, they take precedence over the scene
+#[derive(Clone, Default)]
+pub struct SettingsOverrides {
+    pub max_depth: Option<u8>,
+    pub max_reflection_depth: Option<u8>,
+    pub max_refraction_depth: Option<u8>,
+    pub max_diffuse_depth: Option<u8>,
+    pub ray_offset: Option<f32>,
+}
+
+impl SettingsOverrides {
+    pub fn apply(&self, settings: &mut RenderSettings) {
+        if let Some(depth) = self.max_depth {
+            settings.max_depth = depth;
+        }
+        if let Some(depth) = self.max_reflection_depth {
+            settings.max_reflection_depth = depth;
+        }
+        if let Some(depth) = self.max_refraction_depth {
+            settings.max_refraction_depth = depth;
+        }
+        if let Some(depth) = self.max_diffuse_depth {
+            settings.max_diffuse_depth = depth;
+        }
+        if self.ray_offset.is_some() {
+            settings.ray_offset = self.ray_offset;
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Long-lived worker threads which render frames tile by tile
+
+use crate::raytracer::Frame;
+use crate::scene::Scene;
+use crate::surface::{Division, Surface};
+
+use num_cpus;
+use scoped_threadpool::Pool;
+
+use std::cmp::Ordering;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+/// Order tiles are handed out to the workers in
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TileOrder {
+    /// Rows top to bottom, each left to right
+    Scanline,
+    /// Rings around the center, so the middle of the frame is done first
+    Spiral,
+    /// Along a Hilbert curve, neighbouring tiles are rendered close in time
+    Hilbert,
+}
+
+impl TileOrder {
+    pub const NAMES: &'static str = "scanline, spiral, hilbert";
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TileOrder::Scanline => "scanline",
+            TileOrder::Spiral => "spiral",
+            TileOrder::Hilbert => "hilbert",
+        }
+    }
+
+    pub fn next(self) -> TileOrder {
+        match self {
+            TileOrder::Scanline => TileOrder::Spiral,
+            TileOrder::Spiral => TileOrder::Hilbert,
+            TileOrder::Hilbert => TileOrder::Scanline,
+        }
+    }
+
+    /// Sorts `tiles` of a `cols` by `rows` grid of `size` pixel tiles
+    fn arrange(self, tiles: &mut [Division], size: u32, cols: u32, rows: u32) {
+        let cell = |t: &Division| (t.x0 / size, t.y0 / size);
+        match self {
+            TileOrder::Scanline => tiles.sort_by_key(|t| (t.y0, t.x0)),
+            TileOrder::Spiral => {
+                let (cx, cy) = ((cols - 1) as f32 / 2., (rows - 1) as f32 / 2.);
+                let key = |t: &Division| {
+                    let (x, y) = cell(t);
+                    let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+                    (dx.abs().max(dy.abs()), dy.atan2(dx))
+                };
+                tiles.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap_or(Ordering::Equal));
+            }
+            TileOrder::Hilbert => {
+                let side = cols.max(rows).next_power_of_two();
+                tiles.sort_by_key(|t| {
+                    let (x, y) = cell(t);
+                    hilbert_index(side, x, y)
+                });
+            }
+        }
+    }
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<TileOrder, String> {
+        Ok(match name {
+            "scanline" => TileOrder::Scanline,
+            "spiral" => TileOrder::Spiral,
+            "hilbert" => TileOrder::Hilbert,
+            _ => {
+                return Err(format!(
+                    "Unknown tile order \"{}\", expected one of: {}",
+                    name,
+                    TileOrder::NAMES
+                ))
+            }
+        })
+    }
+}
+
+/// Distance along the Hilbert curve filling a `side` by `side` grid, `side` is a power of two
+fn hilbert_index(side: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut d = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = u32::from(x & s > 0);
+        let ry = u32::from(y & s > 0);
+        d += u64::from(s) * u64::from(s) * u64::from((3 * rx) ^ ry);
+        // Turn the quadrant so the curve inside it starts and ends at the right corners
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+#[derive(Clone)]
+pub struct RendererOptions {
+    /// Worker threads, 0 for one per CPU
+    pub threads: u32,
+    /// Side of the square tiles in pixels
+    pub tile_size: u32,
+    pub order: TileOrder,
+}
+
+impl Default for RendererOptions {
+    fn default() -> RendererOptions {
+        RendererOptions {
+            threads: 0,
+            tile_size: 32,
+            order: TileOrder::Spiral,
+        }
+    }
+}
+
+fn worker_count(threads: u32) -> u32 {
+    if threads == 0 {
+        num_cpus::get() as u32
+    } else {
+        threads
+    }
+}
+
+/// Keeps its threads between frames, each worker takes the next tile from
+/// a shared queue as soon as it's done with the last one
+pub struct Renderer {
+    pool: Pool,
+    tile_size: u32,
+    order: TileOrder,
+}
+
+impl Renderer {
+    pub fn new(options: &RendererOptions) -> Renderer {
+        Renderer {
+            pool: Pool::new(worker_count(options.threads)),
+            tile_size: options.tile_size.max(1),
+            order: options.order,
+        }
+    }
+
+    pub fn threads(&self) -> u32 {
+        self.pool.thread_count()
+    }
+
+    /// Restarts the workers if their number changes, 0 means one per CPU
+    pub fn set_threads(&mut self, threads: u32) {
+        let threads = worker_count(threads);
+        if threads != self.pool.thread_count() {
+            self.pool = Pool::new(threads);
+        }
+    }
+
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    pub fn set_tile_size(&mut self, size: u32) {
+        self.tile_size = size.max(1);
+    }
+
+    pub fn order(&self) -> TileOrder {
+        self.order
+    }
+
+    pub fn set_order(&mut self, order: TileOrder) {
+        self.order = order;
+    }
+
+    pub fn render(&mut self, scene: &Scene) -> Vec<u8> {
+        let frame = Frame::new(scene);
+        let surf = Surface::new(scene.cam.width, scene.cam.height);
+        let size = self.tile_size;
+        let mut tiles = surf.divide(size, size);
+        let cols = surf.w.div_ceil(size).max(1);
+        let rows = surf.h.div_ceil(size).max(1);
+        self.order.arrange(&mut tiles, size, cols, rows);
+
+        let next = AtomicUsize::new(0);
+        let workers = self.pool.thread_count();
+        self.pool.scoped(|scope| {
+            for _ in 0..workers {
+                scope.execute(|| {
+                    while let Some(tile) = tiles.get(next.fetch_add(1, AtomicOrdering::Relaxed)) {
+                        frame.render_tile(tile);
+                    }
+                });
+            }
+        });
+        surf.pixels
+    }
+}
@@ -28,7 +28,8 @@ impl ResourceLoader {
     pub fn resolve_path(&self, path: &str) -> PathBuf {
         if path.starts_with(&self.res_prefix) {
             let rel_path = path.replacen(&self.res_prefix, "", 1);
-            self.res_dir.join(rel_path).canonicalize().unwrap()
+            let path = self.res_dir.join(rel_path);
+            path.canonicalize().unwrap_or(path)
         } else {
             PathBuf::from(path)
         }
@@ -0,0 +1,103 @@
+use std::f32::consts::PI;
+
+/// Van der Corput radical inverse of `i` in the given base
+pub fn radical_inverse(mut i: u32, base: u32) -> f32 {
+    let inv_base = 1. / base as f32;
+    let mut inv = inv_base;
+    let mut result = 0.;
+    while i > 0 {
+        result += (i % base) as f32 * inv;
+        i /= base;
+        inv *= inv_base;
+    }
+    result
+}
+
+/// Cheap integer hash, used to decorrelate sample patterns between pixels
+pub fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+/// 2D Halton point number `i`, shifted by a per-pixel offset
+/// (Cranley-Patterson rotation) so neighbouring pixels use different points
+pub fn pixel_sample(i: u32, x: u32, y: u32) -> (f32, f32) {
+    let seed = hash(x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663));
+    let du = (seed & 0xffff) as f32 / 65536.;
+    let dv = (seed >> 16) as f32 / 65536.;
+    let u = radical_inverse(i, 2) + du;
+    let v = radical_inverse(i, 3) + dv;
+    (u.fract(), v.fract())
+}
+
+/// 1D sample number `i` for the given pixel, independent from `pixel_sample`
+pub fn time_sample(i: u32, x: u32, y: u32) -> f32 {
+    let seed = hash(x.wrapping_mul(83_492_791) ^ y.wrapping_mul(2_654_435_761));
+    (radical_inverse(i, 5) + seed as f32 / 4_294_967_296.).fract()
+}
+
+/// Maps a point of the unit square onto the unit disk, preserving
+/// stratification (Shirley & Chiu concentric mapping)
+pub fn concentric_disk(u: f32, v: f32) -> (f32, f32) {
+    let a = 2. * u - 1.;
+    let b = 2. * v - 1.;
+    if a == 0. && b == 0. {
+        return (0., 0.);
+    }
+    let (r, phi) = if a.abs() > b.abs() {
+        (a, PI / 4. * (b / a))
+    } else {
+        (b, PI / 2. - PI / 4. * (a / b))
+    };
+    (r * phi.cos(), r * phi.sin())
+}
+
+/// Maps a point of the unit square onto a regular polygon inscribed
+/// into the unit circle
+pub fn polygon(u: f32, v: f32, blades: u32, rotation: f32) -> (f32, f32) {
+    let blades = blades.max(3);
+    let step = 2. * PI / blades as f32;
+    let scaled = u * blades as f32;
+    let blade = scaled.floor().min(blades as f32 - 1.);
+    let u = scaled - blade;
+
+    let a0 = rotation.to_radians() + blade * step;
+    let a1 = a0 + step;
+    let s = u.sqrt();
+    let (w0, w1) = (s * (1. - v), s * v);
+    (w0 * a0.cos() + w1 * a1.cos(), w0 * a0.sin() + w1 * a1.sin())
+}
+
+/// Small seeded random number generator (SplitMix64), the same seed always
+/// gives the same numbers
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform number in [0, 1)
+    pub fn float(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform number in [`min`, `max`)
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.float()
+    }
+}
@@ -1,30 +1,500 @@
-use crate::object::Object;
+use crate::object::{flatten, Object};
 use crate::light::Light;
-use crate::camera::Camera;
+use crate::animation::SetPosition;
+use crate::builtin;
+use crate::camera::{Camera, CameraAnimation, Target};
+use crate::composition::{self, Instance, Prefabs};
+use crate::geometry::Geometry;
+use crate::gltf_import;
+use crate::keyframe::{Property, Track};
+use crate::material::{MaterialLibrary, MaterialRef};
+use crate::math::{Vec3f, Mat4f, set_rotation, set_scale, set_translation, translation};
+use crate::mesh_import::{self, Mesh};
+use crate::migration::{self, CURRENT_VERSION};
+use crate::pbrt_import;
+use crate::render_settings::RenderSettings;
+use crate::resource::ResourceLoader;
+use crate::validation::{validate, Issue, Severity};
 
+use ron::error::SpannedError;
 use serde::{Serialize, Deserialize};
 
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        file: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// File in a foreign format couldn't be read
+    Import {
+        file: PathBuf,
+        message: String,
+    },
+    /// File includes itself, directly or through other files
+    IncludeCycle {
+        file: PathBuf,
+    },
+    /// Scene parsed, but has values the renderer can't handle
+    Invalid {
+        file: PathBuf,
+        issues: Vec<Issue>,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SceneError::Io {
+                ref file,
+                ref error,
+            } => {
+                write!(f, "Can't read {}: {}", file.display(), error)
+            }
+            SceneError::Parse {
+                ref file,
+                line,
+                column,
+                ref message,
+            } => write!(f, "{}:{}:{}: {}", file.display(), line, column, message),
+            SceneError::Import {
+                ref file,
+                ref message,
+            } => {
+                write!(f, "Can't import {}: {}", file.display(), message)
+            }
+            SceneError::IncludeCycle { ref file } => {
+                write!(f, "{}: included recursively", file.display())
+            }
+            SceneError::Invalid {
+                ref file,
+                ref issues,
+            } => {
+                write!(f, "{} is invalid:", file.display())?;
+                for issue in issues {
+                    write!(f, "\n{}", issue)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Scene {
+    /// Format version, older files are upgraded on load
+    #[serde(default)]
+    pub version: u32,
+
     pub cam: Camera,
+
+    /// Depth limits, ray offset and backgrounds of the ray tracer
+    #[serde(default)]
+    pub settings: RenderSettings,
+
     pub objects: Vec<Object>,
     pub lights: Vec<Light>,
+
+    /// Scene fragment files, relative to the scene file or `~res:`
+    #[serde(default)]
+    pub includes: Vec<String>,
+
+    /// Object groups which can be placed several times with `instances`
+    #[serde(default)]
+    pub prefabs: Prefabs,
+
+    #[serde(default)]
+    pub instances: Vec<Instance>,
+
+    /// Materials objects can refer to by name
+    #[serde(default)]
+    pub materials: MaterialLibrary,
+
+    /// Material library files, relative to the scene file or `~res:`,
+    /// entries of `materials` take precedence over them
+    #[serde(default)]
+    pub material_libraries: Vec<String>,
+
+    /// Fly-through path and look-at target of the camera
+    #[serde(default)]
+    pub cam_animation: Option<CameraAnimation>,
+
+    /// Keyframe animation of scene properties
+    #[serde(default)]
+    pub tracks: Vec<Track>,
+
+    /// Animation time in seconds the scene was last evaluated at
+    #[serde(skip)]
+    pub time: f32,
+
+    #[serde(skip)]
+    animation_dirty: bool,
+
+    /// Files the scene was read from
+    #[serde(skip)]
+    pub sources: Vec<PathBuf>,
+
+    /// Problems found on load which don't prevent rendering
+    #[serde(skip)]
+    pub warnings: Vec<Issue>,
+}
+
+pub fn read(path: &Path) -> Result<String, SceneError> {
+    std::fs::read_to_string(path).map_err(|error| SceneError::Io {
+        file: path.to_path_buf(),
+        error,
+    })
+}
+
+pub fn parse_error(path: &Path, e: SpannedError) -> SceneError {
+    SceneError::Parse {
+        file: path.to_path_buf(),
+        line: e.span.start.line,
+        column: e.span.start.col,
+        message: e.code.to_string(),
+    }
+}
+
+pub fn is_gltf(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("gltf") || ext.eq_ignore_ascii_case("glb"),
+        None => false,
+    }
+}
+
+pub fn is_pbrt(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("pbrt"),
+        None => false,
+    }
+}
+
+/// Reads a scene in a foreign format, `None` if the file isn't one
+pub fn import(path: &Path) -> Option<Result<(Scene, Vec<Issue>), SceneError>> {
+    if builtin::name(path).is_some() {
+        Some(builtin::load(path))
+    } else if is_gltf(path) {
+        Some(gltf_import::import(path))
+    } else if is_pbrt(path) {
+        Some(pbrt_import::import(path))
+    } else {
+        None
+    }
+}
+
+/// Imports a foreign file and writes it as a scene, next to it unless `out` is given
+pub fn convert(file: &str, out: Option<&str>) -> Result<(), String> {
+    let imported =
+        import(Path::new(file)).ok_or_else(|| format!("{}: not a glTF or PBRT file", file))?;
+    let (scene, warnings) = imported.map_err(|e| e.to_string())?;
+    for w in &warnings {
+        eprintln!("{}", w);
+    }
+    let target = match out {
+        Some(out) => PathBuf::from(out),
+        None => Path::new(file).with_extension("ron"),
+    };
+    let text = migration::serialize(&scene).map_err(|e| e.to_string())?;
+    std::fs::write(&target, text)
+        .map_err(|e| format!("Can't write {}: {}", target.display(), e))?;
+    println!("{}: written to {}", file, target.display());
+    Ok(())
+}
+
+/// Path of a file referenced from a scene in `dir`
+pub fn resolve_path(dir: &Path, path: &str) -> PathBuf {
+    let resolved = ResourceLoader::new().resolve_path(path);
+    if resolved.is_relative() {
+        dir.join(resolved)
+    } else {
+        resolved
+    }
 }
 
 impl Scene {
-    pub fn update_objects(&mut self) {
+    pub fn new(cam: Camera, objects: Vec<Object>, lights: Vec<Light>) -> Scene {
+        let mut scene = Scene {
+            version: CURRENT_VERSION,
+            cam,
+            settings: RenderSettings::default(),
+            objects,
+            lights,
+            includes: Vec::new(),
+            prefabs: Prefabs::new(),
+            instances: Vec::new(),
+            materials: MaterialLibrary::new(),
+            material_libraries: Vec::new(),
+            cam_animation: None,
+            tracks: Vec::new(),
+            time: 0.,
+            animation_dirty: false,
+            sources: Vec::new(),
+            warnings: Vec::new(),
+        };
+        scene.update_world();
+        scene
+    }
+
+    /// Reads and validates the scene, warnings are kept in `warnings`.
+    /// `.gltf` and `.glb` files are imported.
+    pub fn load(path: &Path) -> Result<Scene, SceneError> {
+        let file = path.to_path_buf();
+        let (mut scene, lines, mut notes) = if let Some(imported) = import(path) {
+            let (scene, warnings) = imported?;
+            (scene, String::new(), warnings)
+        } else {
+            let lines = read(path)?;
+            let parsed =
+                migration::detect_version(&lines).and_then(|v| Ok((v, migration::parse(&lines)?)));
+            let (version, scene) = parsed.map_err(|e| parse_error(path, e))?;
+            let mut notes = Vec::new();
+            if version < CURRENT_VERSION {
+                notes.push(Issue {
+                    severity: Severity::Warning,
+                    item: "scene".to_string(),
+                    field: "version".to_string(),
+                    message: format!(
+                        "old format version {}, upgrade the file with `rt migrate`",
+                        version
+                    ),
+                    line: None,
+                });
+            }
+            (scene, lines, notes)
+        };
+
+        let libraries = composition::include(&mut scene, path)?;
+        composition::expand_instances(&mut scene);
+        scene.objects = flatten(std::mem::take(&mut scene.objects));
+        scene.load_material_libraries(&libraries)?;
+        notes.append(&mut scene.load_meshes()?);
+        scene.resolve_materials();
+        scene.update_world();
+
+        let (errors, warnings): (Vec<Issue>, Vec<Issue>) = validate(&scene, &lines)
+            .into_iter()
+            .partition(|i| i.severity == Severity::Error);
+        if !errors.is_empty() {
+            return Err(SceneError::Invalid {
+                file,
+                issues: errors,
+            });
+        }
+        scene.warnings = warnings;
+        scene.warnings.append(&mut notes);
+        scene.sources.insert(0, file);
+        Ok(scene)
+    }
+
+    /// Merges library files into `materials`, names already there are kept
+    fn load_material_libraries(&mut self, files: &[PathBuf]) -> Result<(), SceneError> {
+        for file in files {
+            let text = read(file)?;
+            let library: MaterialLibrary =
+                ron::de::from_str(&text).map_err(|e| parse_error(file, e))?;
+            for (name, mat) in library {
+                self.materials.entry(name).or_insert(mat);
+            }
+            self.sources.push(file.clone());
+        }
+        Ok(())
+    }
+
+    /// Adds the triangles of mesh files to the objects referencing them,
+    /// returns warnings about triangles that were left out
+    fn load_meshes(&mut self) -> Result<Vec<Issue>, SceneError> {
+        let mut meshes: BTreeMap<String, Mesh> = BTreeMap::new();
+        let mut notes = Vec::new();
         for obj in &mut self.objects {
-            obj.update_animation();
+            let file = match obj.mesh {
+                Some(ref file) => file.clone(),
+                None => continue,
+            };
+            if !meshes.contains_key(&file) {
+                let mesh = mesh_import::load(Path::new(&file))?;
+                if mesh.degenerate > 0 {
+                    notes.push(Issue {
+                        severity: Severity::Warning,
+                        item: format!("object \"{}\"", obj.name),
+                        field: "mesh".to_string(),
+                        message: format!(
+                            "{} degenerate triangles in {} left out",
+                            mesh.degenerate, file
+                        ),
+                        line: None,
+                    });
+                }
+                self.sources.push(PathBuf::from(&file));
+                meshes.insert(file.clone(), mesh);
+            }
+            obj.shapes.extend(
+                meshes[&file]
+                    .triangles
+                    .iter()
+                    .map(|&t| Geometry::Triangle(t)),
+            );
         }
+        Ok(notes)
     }
 
-    pub fn any_animation_dirty(&self) -> bool {
-        for o in &self.objects {
-            match &o.animation {
-                Some(a) => if a.dirty == true { return true },
-                None => {}
+    /// Gives every object its material, unknown names are left to validation
+    fn resolve_materials(&mut self) {
+        for obj in &mut self.objects {
+            match obj.material {
+                MaterialRef::Inline(mat) => obj.mat = mat,
+                MaterialRef::Named(ref name) => {
+                    if let Some(mat) = self.materials.get(name) {
+                        obj.mat = *mat;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Composes object transforms with their parents, parents come first in the list
+    fn update_world(&mut self) {
+        for i in 0..self.objects.len() {
+            let parent = match self.objects[i].parent {
+                Some(p) => self.objects[p].world,
+                None => Mat4f::identity(),
+            };
+            self.objects[i].world = parent * self.objects[i].transform;
+        }
+    }
+
+    /// Saves the camera and object placements the next frame moves from
+    pub fn begin_frame(&mut self) {
+        self.cam.begin_frame();
+        for obj in &mut self.objects {
+            obj.prev_transform = Some(obj.world);
+        }
+    }
+
+    /// Puts every animated object, light and the camera where they are at `t` seconds,
+    /// the result doesn't depend on previously evaluated times
+    pub fn evaluate_at(&mut self, t: f32) {
+        let mut animated = false;
+        for obj in &mut self.objects {
+            animated |= obj.animation.is_some();
+            obj.evaluate_animation(t);
+        }
+        for light in &mut self.lights {
+            animated |= light.motion.is_some();
+            light.evaluate_motion(t);
+        }
+        for i in 0..self.tracks.len() {
+            if let Some(value) = self.tracks[i].sample(t) {
+                let property = self.tracks[i].property.clone();
+                self.set_property(&property, value);
+                animated = true;
+            }
+        }
+        self.update_world();
+        if self.cam_animation.is_some() {
+            self.evaluate_camera(t);
+            animated = true;
+        }
+        self.animation_dirty = animated && t != self.time;
+        self.time = t;
+    }
+
+    /// Camera animation runs after everything else so it can track moved objects
+    fn evaluate_camera(&mut self, t: f32) {
+        let anim = match self.cam_animation {
+            Some(ref a) => a,
+            None => return,
+        };
+        if let Some(ref motion) = anim.motion {
+            self.cam.set_position(motion.position_at(t));
+        }
+        let target = match anim.target {
+            Some(Target::Point(p)) => Some(p),
+            Some(Target::Object(ref name)) => self
+                .objects
+                .iter()
+                .find(|o| &o.name == name)
+                .map(|o| translation(&o.world)),
+            None => None,
+        };
+        if let Some(p) = target {
+            self.cam.look_at(p);
+        }
+    }
+
+    fn object_mut(&mut self, name: &str) -> Option<&mut Object> {
+        self.objects.iter_mut().find(|o| o.name == name)
+    }
+
+    /// Sets a property a keyframe track drives, missing targets are ignored
+    fn set_property(&mut self, property: &Property, value: Vec3f) {
+        use crate::keyframe::Property::*;
+        match *property {
+            Position(ref name) => {
+                if let Some(o) = self.object_mut(name) {
+                    set_translation(&mut o.transform, value);
+                }
             }
+            Rotation(ref name) => {
+                if let Some(o) = self.object_mut(name) {
+                    set_rotation(&mut o.transform, value);
+                }
+            }
+            Scale(ref name) => {
+                if let Some(o) = self.object_mut(name) {
+                    set_scale(&mut o.transform, value);
+                }
+            }
+            Ambient(ref name) => {
+                if let Some(o) = self.object_mut(name) {
+                    o.mat.ambient = value;
+                }
+            }
+            Diffuse(ref name) => {
+                if let Some(o) = self.object_mut(name) {
+                    o.mat.diffuse = value;
+                }
+            }
+            Specular(ref name) => {
+                if let Some(o) = self.object_mut(name) {
+                    o.mat.specular = value;
+                }
+            }
+            Shininess(ref name) => {
+                if let Some(o) = self.object_mut(name) {
+                    o.mat.shininess = value.x;
+                }
+            }
+            Reflection(ref name) => {
+                if let Some(o) = self.object_mut(name) {
+                    o.mat.reflection = value.x;
+                }
+            }
+            LightPosition(i) => {
+                if let Some(l) = self.lights.get_mut(i) {
+                    l.pos = value;
+                }
+            }
+            LightColor(i) => {
+                if let Some(l) = self.lights.get_mut(i) {
+                    l.color = value;
+                }
+            }
+            CameraEye => self.cam.eye = value,
+            CameraFov => self.cam.fov = value.x,
         }
-        false
+    }
+
+    pub fn any_animation_dirty(&self) -> bool {
+        self.animation_dirty
     }
 }
@@ -1,97 +1,447 @@
+//! Procedurally generated scenes, written out by `rt generate`
+
 use crate::animation::Animation;
-use crate::camera::CamBuilder;
+use crate::camera::{Bokeh, CamBuilder, Camera, Projection};
+use crate::geometry::{Cuboid, Geometry, Sphere, Triangle};
 use crate::light::Light;
-use crate::material::Material;
-use crate::math::Vec3f;
-use crate::object::{new_box, new_sphere, new_square, new_triangle, Object};
+use crate::material::{Material, MaterialRef};
+use crate::math::{set_translation, Mat4f, Vec3f};
+use crate::migration;
+use crate::object::Object;
+use crate::sampling::Rng;
 use crate::scene::Scene;
+use crate::validation::{self, Severity};
 
-use ron::ser::{to_string_pretty, PrettyConfig};
+use std::str::FromStr;
 
-#[allow(dead_code)]
-pub fn generate_scene() {
-    let red = Material {
-        ambient: Vec3f::new(0.1, 0.1, 0.1),
-        diffuse: Vec3f::new(1., 0., 0.),
-        specular: Vec3f::new(1., 1., 1.),
-        shininess: 30.0,
-        reflection: 0.8,
-        refraction: 0.,
-    };
+/// Kinds of scenes the generator can build
+#[derive(Clone, Copy, PartialEq)]
+pub enum Template {
+    /// Spheres of random size and material scattered over a floor
+    Spheres,
+    /// Red and green walled box lit from under the ceiling
+    Cornell,
+    /// Grid of spheres going from matte to mirror and from dull to shiny
+    Materials,
+    /// Lots of random spheres, boxes and triangles to measure rendering speed
+    Stress,
+}
 
-    let blue = Material {
-        ambient: Vec3f::new(0.1, 0.1, 0.1),
-        diffuse: Vec3f::new(0., 0.3, 1.),
-        specular: Vec3f::new(1., 1., 1.),
-        shininess: 10.0,
-        reflection: 0.4,
-        refraction: 1.025,
-    };
+impl Template {
+    pub const NAMES: &'static str = "spheres, cornell, materials, stress";
 
-    let green = Material {
-        ambient: Vec3f::new(0.1, 0.1, 0.1),
-        diffuse: Vec3f::new(0., 1., 0.3),
-        specular: Vec3f::new(1., 1., 1.),
-        shininess: 10.0,
-        reflection: 0.8,
-        refraction: 0.,
-    };
+    /// What `count` means for the template when it's not given
+    pub fn default_count(self) -> usize {
+        match self {
+            Template::Spheres => 40,
+            Template::Cornell => 0,
+            Template::Materials => 5,
+            Template::Stress => 1000,
+        }
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Template, String> {
+        Ok(match name {
+            "spheres" => Template::Spheres,
+            "cornell" => Template::Cornell,
+            "materials" => Template::Materials,
+            "stress" => Template::Stress,
+            _ => {
+                return Err(format!(
+                    "Unknown template \"{}\", expected one of: {}",
+                    name,
+                    Template::NAMES
+                ))
+            }
+        })
+    }
+}
+
+/// Builds a scene from `template`, `count` is the number of spheres for
+/// `spheres`, the grid side for `materials` and the number of objects for
+/// `stress`, the same seed always gives the same scene
+pub fn generate(template: Template, count: usize, seed: u64) -> Scene {
+    let mut rng = Rng::new(seed);
+    match template {
+        Template::Spheres => sphere_field(count, &mut rng),
+        Template::Cornell => cornell_box(),
+        Template::Materials => material_grid(count, &mut rng),
+        Template::Stress => stress(count, &mut rng),
+    }
+}
 
-    let camera = CamBuilder::new()
-        .eye(Vec3f::new(0., 0., 60.))
-        .center(Vec3f::new(0., 0., 59.))
-        .fov(30.)
-        .width(128)
-        .height(128)
+/// Generates a scene and writes it to `out`, or to stdout without it
+pub fn write(
+    template: Template,
+    count: Option<usize>,
+    seed: u64,
+    out: Option<&str>,
+) -> Result<(), String> {
+    let scene = generate(
+        template,
+        count.unwrap_or_else(|| template.default_count()),
+        seed,
+    );
+    let text = migration::serialize(&scene).map_err(|e| e.to_string())?;
+    let errors: Vec<String> = validation::validate(&scene, &text)
+        .into_iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .map(|issue| issue.to_string())
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    match out {
+        Some(out) => {
+            std::fs::write(out, text).map_err(|e| format!("Can't write {}: {}", out, e))?;
+            println!(
+                "Scene with {} objects written to {}",
+                scene.objects.len(),
+                out
+            );
+        }
+        None => println!("{}", text),
+    }
+    Ok(())
+}
+
+/// Camera with y pointing down like in the rest of the scenes
+pub fn camera(eye: Vec3f, target: Vec3f, fov: f32, width: u32, height: u32) -> Camera {
+    let mut cam = CamBuilder::new()
+        .eye(eye)
+        .center(target)
         .up(Vec3f::new(0., -1., 0.))
+        .fov(fov)
+        .width(width)
+        .height(height)
+        .projection(Projection::Perspective)
+        .stereo(None)
+        .aperture(0.)
+        .focal_distance((target - eye).norm())
+        .autofocus(false)
+        .bokeh(Bokeh::Circle)
+        .samples(1)
+        .shutter(0.)
         .build();
+    // Sets the view angles the viewer's mouse look starts from
+    cam.look_at(target);
+    cam
+}
+
+pub fn material(
+    diffuse: Vec3f,
+    specular: f32,
+    shininess: f32,
+    reflection: f32,
+    refraction: f32,
+) -> Material {
+    Material {
+        ambient: Vec3f::new(0.1, 0.1, 0.1),
+        diffuse,
+        specular: Vec3f::new(specular, specular, specular),
+        shininess,
+        reflection,
+        refraction,
+    }
+}
+
+/// Reference to the scene material `name`
+pub fn named(name: &str) -> MaterialRef {
+    MaterialRef::Named(name.to_string())
+}
+
+fn at(pos: Vec3f) -> Mat4f {
+    let mut t = Mat4f::identity();
+    set_translation(&mut t, pos);
+    t
+}
+
+pub fn sphere(name: &str, center: Vec3f, radius: f32, mat: MaterialRef) -> Object {
+    Object::new(
+        name,
+        vec![Geometry::Sphere(Sphere { radius })],
+        mat,
+        at(center),
+    )
+}
 
-    let mut sphere1 = new_sphere("s1", Vec3f::new(15., 15., 15.), 5., green);
-    let mut box1 = new_box(
-        "b1",
-        Vec3f::new(10., 10., -20.),
-        Vec3f::new(10., 10., 10.),
-        red,
+/// Axis aligned box from `min` to `min + extent`
+pub fn cuboid(name: &str, min: Vec3f, extent: Vec3f, mat: MaterialRef) -> Object {
+    Object::new(
+        name,
+        vec![Geometry::Cuboid(Cuboid { extent })],
+        mat,
+        at(min),
+    )
+}
+
+/// Flat quad with the corners going around its edge
+pub fn quad(name: &str, corners: [Vec3f; 4], mat: MaterialRef) -> Object {
+    let [a, b, c, d] = corners;
+    let shapes = vec![
+        Geometry::Triangle(Triangle {
+            a,
+            b,
+            c,
+            colors: None,
+        }),
+        Geometry::Triangle(Triangle {
+            a,
+            b: c,
+            c: d,
+            colors: None,
+        }),
+    ];
+    Object::new(name, shapes, mat, Mat4f::identity())
+}
+
+/// Horizontal square of side `size` at height `y`
+pub fn floor(name: &str, y: f32, size: f32, mat: MaterialRef) -> Object {
+    let s = size / 2.;
+    let corners = [
+        Vec3f::new(-s, y, -s),
+        Vec3f::new(s, y, -s),
+        Vec3f::new(s, y, s),
+        Vec3f::new(-s, y, s),
+    ];
+    quad(name, corners, mat)
+}
+
+pub fn light(pos: Vec3f, intensity: f32) -> Light {
+    Light {
+        pos,
+        color: Vec3f::new(intensity, intensity, intensity),
+        motion: None,
+    }
+}
+
+fn random_color(rng: &mut Rng) -> Vec3f {
+    Vec3f::new(
+        rng.range(0.1, 0.9),
+        rng.range(0.1, 0.9),
+        rng.range(0.1, 0.9),
+    )
+}
+
+/// Matte, shiny, mirror or glass material with a random color
+fn random_material(rng: &mut Rng) -> Material {
+    let color = random_color(rng);
+    let kind = rng.float();
+    if kind < 0.4 {
+        material(color, 0.2, 5., 0., 0.)
+    } else if kind < 0.7 {
+        material(color, 1., rng.range(20., 200.), 0., 0.)
+    } else if kind < 0.9 {
+        material(color, 1., 100., rng.range(0.3, 0.9), 0.)
+    } else {
+        material(color, 1., 200., 0.1, 1.5)
+    }
+}
+
+/// The world has y pointing down, so the spheres lie on the floor at y = 0
+/// with negative y centers
+fn sphere_field(count: usize, rng: &mut Rng) -> Scene {
+    const SPREAD: f32 = 30.;
+    const ATTEMPTS: usize = 100;
+    const HOP_EVERY: usize = 5;
+
+    let mut placed: Vec<(Vec3f, f32)> = Vec::new();
+    let mut objects = Vec::new();
+    for i in 0..count {
+        for _ in 0..ATTEMPTS {
+            let radius = rng.range(1., 3.5);
+            let center = Vec3f::new(
+                rng.range(-SPREAD, SPREAD),
+                -radius,
+                rng.range(-SPREAD, SPREAD),
+            );
+            if placed
+                .iter()
+                .any(|&(c, r)| (c - center).norm() < r + radius + 0.2)
+            {
+                continue;
+            }
+            placed.push((center, radius));
+            let name = format!("sphere{}", i);
+            let mut object = sphere(
+                &name,
+                center,
+                radius,
+                MaterialRef::Inline(random_material(rng)),
+            );
+            // Every few spheres hop so the scene can be rendered as an animation too
+            if i % HOP_EVERY == 0 {
+                let hop = Vec3f::new(0., -rng.range(2., 8.), 0.);
+                object.set_animation(Animation::new(&object, &[hop], rng.range(3., 8.)));
+            }
+            objects.push(object);
+            break;
+        }
+    }
+    objects.push(floor("floor", 0., 200., named("floor")));
+
+    let cam = camera(
+        Vec3f::new(0., -18., 70.),
+        Vec3f::new(0., -2., 0.),
+        25.,
+        128,
+        128,
     );
-    let mut sphere3 = new_sphere("s5", Vec3f::new(-15., 15., 15.), 5., blue);
-    let mut sphere4 = new_sphere("s6", Vec3f::new(-15., 15., -15.), 5., red);
-    let sphere5 = new_sphere("s3", Vec3f::new(15., -15., 15.), 5., green);
-    let sphere6 = new_sphere("s4", Vec3f::new(15., -15., -15.), 5., blue);
-    let sphere7 = new_sphere("s7", Vec3f::new(-15., -15., 15.), 5., red);
-    let sphere8 = new_sphere("s8", Vec3f::new(-15., -15., -15.), 5., green);
-    sphere1.set_animation(Animation::new(&sphere1, &[Vec3f::new(0., -10., 0.)]));
-    box1.set_animation(Animation::new(&box1, &[Vec3f::new(0., 7., 0.)]));
-    sphere3.set_animation(Animation::new(&sphere3, &[Vec3f::new(0., -8., 0.)]));
-    sphere4.set_animation(Animation::new(&sphere4, &[Vec3f::new(0., 5., 0.)]));
-
-    let triangle = new_triangle(
-        "tri1",
-        Vec3f::new(0., -10., -10.),
-        Vec3f::new(-10., 10., 0.),
-        Vec3f::new(10., 10., 0.),
-        blue,
+    let lights = vec![
+        light(Vec3f::new(40., -80., 40.), 0.7),
+        light(Vec3f::new(-50., -40., -20.), 0.4),
+    ];
+    let mut scene = Scene::new(cam, objects, lights);
+    scene.materials.insert(
+        "floor".to_string(),
+        material(Vec3f::new(0.6, 0.6, 0.6), 0.1, 5., 0., 0.),
     );
+    scene
+}
 
-    let objects: Vec<Object> = vec![
-        sphere1,
-        box1,
-        sphere3,
-        sphere4,
-        sphere5,
-        sphere6,
-        sphere7,
-        triangle,
-        sphere8,
-        new_square("square", Vec3f::new(0., 30., 0.), 128, blue),
+/// Box of 20 units with the front side open, the camera looks in through it
+pub fn cornell_box() -> Scene {
+    let s = 10.;
+    let h = -20.;
+    let v = |x: f32, y: f32, z: f32| Vec3f::new(x, y, z);
+    let objects = vec![
+        quad(
+            "floor",
+            [v(-s, 0., -s), v(s, 0., -s), v(s, 0., s), v(-s, 0., s)],
+            named("white"),
+        ),
+        quad(
+            "ceiling",
+            [v(-s, h, -s), v(s, h, -s), v(s, h, s), v(-s, h, s)],
+            named("white"),
+        ),
+        quad(
+            "back",
+            [v(-s, 0., -s), v(s, 0., -s), v(s, h, -s), v(-s, h, -s)],
+            named("white"),
+        ),
+        quad(
+            "left",
+            [v(-s, 0., -s), v(-s, 0., s), v(-s, h, s), v(-s, h, -s)],
+            named("red"),
+        ),
+        quad(
+            "right",
+            [v(s, 0., -s), v(s, 0., s), v(s, h, s), v(s, h, -s)],
+            named("green"),
+        ),
+        cuboid("tall", v(-6.5, -12., -6.), v(6., 12., 6.), named("white")),
+        cuboid("short", v(1., -6., -1.), v(5., 6., 5.), named("white")),
     ];
+    let cam = camera(v(0., -10., 38.), v(0., -10., 0.), 15., 128, 128);
+    let mut scene = Scene::new(cam, objects, vec![light(v(0., -18., 5.), 1.)]);
+    let matte = |color| material(color, 0.1, 5., 0., 0.);
+    scene
+        .materials
+        .insert("white".to_string(), matte(v(0.75, 0.75, 0.75)));
+    scene
+        .materials
+        .insert("red".to_string(), matte(v(0.75, 0.1, 0.1)));
+    scene
+        .materials
+        .insert("green".to_string(), matte(v(0.1, 0.75, 0.1)));
+    scene
+}
+
+/// `side` by `side` spheres, reflection grows to the right and shininess to the back
+fn material_grid(side: usize, rng: &mut Rng) -> Scene {
+    const RADIUS: f32 = 2.;
+    const SPACING: f32 = 5.;
 
-    let light1 = Light {
-        pos: Vec3f::new(0., -60., 0.),
-        color: Vec3f::new(1., 1., 1.),
+    let side = side.max(1);
+    let color = random_color(rng);
+    let offset = (side - 1) as f32 * SPACING / 2.;
+    let step = |i: usize| {
+        if side > 1 {
+            i as f32 / (side - 1) as f32
+        } else {
+            0.
+        }
     };
-    let lights: Vec<Light> = vec![light1];
+    let mut objects = Vec::new();
+    for row in 0..side {
+        for column in 0..side {
+            let reflection = 0.9 * step(column);
+            // Exponents from 2 to 500, evenly spread on a log scale
+            let shininess = 2. * 250f32.powf(step(row));
+            let center = Vec3f::new(
+                column as f32 * SPACING - offset,
+                -RADIUS,
+                offset - row as f32 * SPACING,
+            );
+            let mat = material(color, 1., shininess, reflection, 0.);
+            let name = format!("sphere_{}_{}", row, column);
+            objects.push(sphere(&name, center, RADIUS, MaterialRef::Inline(mat)));
+        }
+    }
+    let size = side as f32 * SPACING;
+    objects.push(floor("floor", 0., size * 4., named("floor")));
 
-    let scene: Scene = Scene{ cam: camera, objects: objects, lights: lights };
-    let s = to_string_pretty(&scene, PrettyConfig::default()).unwrap();
-    println!("{}", s);
+    let distance = size * 1.6 + 10.;
+    let eye = Vec3f::new(0., -distance * 0.6, distance);
+    let cam = camera(eye, Vec3f::new(0., -RADIUS, 0.), 20., 128, 128);
+    let lights = vec![light(Vec3f::new(-size, -size * 2., size * 2.), 0.8)];
+    let mut scene = Scene::new(cam, objects, lights);
+    scene.materials.insert(
+        "floor".to_string(),
+        material(Vec3f::new(0.5, 0.5, 0.5), 0.1, 5., 0., 0.),
+    );
+    scene
+}
+
+/// `count` spheres, boxes and triangles scattered in a cube, for timing
+fn stress(count: usize, rng: &mut Rng) -> Scene {
+    const HALF: f32 = 50.;
+
+    let mut objects = Vec::with_capacity(count);
+    for i in 0..count {
+        let pos = Vec3f::new(
+            rng.range(-HALF, HALF),
+            rng.range(-HALF, HALF),
+            rng.range(-HALF, HALF),
+        );
+        let mat = MaterialRef::Inline(random_material(rng));
+        let object = match rng.next_u64() % 3 {
+            0 => sphere(&format!("sphere{}", i), pos, rng.range(0.5, 2.5), mat),
+            1 => {
+                let extent = Vec3f::new(rng.range(1., 4.), rng.range(1., 4.), rng.range(1., 4.));
+                cuboid(&format!("box{}", i), pos, extent, mat)
+            }
+            _ => {
+                let mut corner =
+                    || pos + Vec3f::new(rng.range(-3., 3.), rng.range(-3., 3.), rng.range(-3., 3.));
+                let (a, b, c) = (corner(), corner(), corner());
+                let shapes = vec![Geometry::Triangle(Triangle {
+                    a,
+                    b,
+                    c,
+                    colors: None,
+                })];
+                Object::new(&format!("triangle{}", i), shapes, mat, Mat4f::identity())
+            }
+        };
+        objects.push(object);
+    }
+
+    let cam = camera(
+        Vec3f::new(0., 0., 160.),
+        Vec3f::new(0., 0., 0.),
+        22.,
+        128,
+        128,
+    );
+    let lights = vec![
+        light(Vec3f::new(0., -200., 100.), 0.6),
+        light(Vec3f::new(150., 50., 150.), 0.4),
+    ];
+    Scene::new(cam, objects, lights)
 }
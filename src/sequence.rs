@@ -0,0 +1,180 @@
+use crate::image_io::{read_ppm, write_ppm};
+use crate::render_settings::SettingsOverrides;
+use crate::renderer::{Renderer, RendererOptions};
+use crate::scene::Scene;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+/// Where rendered frames go besides the numbered image files
+pub enum Encoder {
+    /// Uncompressed YUV4MPEG2 video file
+    Y4m(PathBuf),
+    /// Shell command reading raw RGB24 frames from its standard input
+    Pipe(String),
+}
+
+pub struct SequenceOptions {
+    pub scene: String,
+    pub out_dir: PathBuf,
+    /// First frame number
+    pub start: u32,
+    /// Frame number after the last one
+    pub end: u32,
+    pub fps: f32,
+    pub encoder: Option<Encoder>,
+    /// Command line render settings, applied over the scene ones
+    pub settings: SettingsOverrides,
+    pub renderer: RendererOptions,
+}
+
+enum Sink {
+    Y4m(BufWriter<File>),
+    Pipe(Child),
+}
+
+impl Sink {
+    fn open(encoder: &Encoder, scene: &Scene, fps: f32) -> io::Result<Sink> {
+        match *encoder {
+            Encoder::Y4m(ref path) => {
+                let mut file = BufWriter::new(File::create(path)?);
+                let (num, den) = frame_rate_ratio(fps);
+                // Without the range tag readers take the samples as limited range
+                writeln!(
+                    file,
+                    "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444 XCOLORRANGE=FULL",
+                    scene.cam.width, scene.cam.height, num, den
+                )?;
+                Ok(Sink::Y4m(file))
+            }
+            Encoder::Pipe(ref cmd) => {
+                let child = Command::new("sh")
+                    .arg("-c")
+                    .arg(cmd)
+                    .stdin(Stdio::piped())
+                    .spawn()?;
+                Ok(Sink::Pipe(child))
+            }
+        }
+    }
+
+    fn write_frame(&mut self, pixels: &[u8]) -> io::Result<()> {
+        match *self {
+            Sink::Y4m(ref mut file) => {
+                file.write_all(b"FRAME\n")?;
+                file.write_all(&rgb_to_yuv444(pixels))
+            }
+            Sink::Pipe(ref mut child) => child.stdin.as_mut().unwrap().write_all(pixels),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Sink::Y4m(mut file) => file.flush(),
+            Sink::Pipe(mut child) => {
+                drop(child.stdin.take());
+                let status = child.wait()?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(io::Error::other(format!("encoder exited with {}", status)))
+                }
+            }
+        }
+    }
+}
+
+/// Frame rate as an integer ratio, e.g. 29.97 becomes 2997:100
+fn frame_rate_ratio(fps: f32) -> (u32, u32) {
+    if fps.fract() == 0.0 {
+        (fps as u32, 1)
+    } else {
+        ((fps * 100.).round() as u32, 100)
+    }
+}
+
+/// Converts packed RGB into planar full range BT.601 YCbCr
+fn rgb_to_yuv444(pixels: &[u8]) -> Vec<u8> {
+    let n = pixels.len() / 3;
+    let mut out = vec![0; n * 3];
+    for (i, px) in pixels.chunks(3).enumerate() {
+        let (r, g, b) = (f32::from(px[0]), f32::from(px[1]), f32::from(px[2]));
+        let y = 0.299 * r + 0.587 * g + 0.114 * b;
+        let u = 128. - 0.168_736 * r - 0.331_264 * g + 0.5 * b;
+        let v = 128. + 0.5 * r - 0.418_688 * g - 0.081_312 * b;
+        out[i] = y.round().clamp(0., 255.) as u8;
+        out[n + i] = u.round().clamp(0., 255.) as u8;
+        out[2 * n + i] = v.round().clamp(0., 255.) as u8;
+    }
+    out
+}
+
+fn frame_path(dir: &Path, frame: u32) -> PathBuf {
+    dir.join(format!("frame_{:05}.ppm", frame))
+}
+
+/// Renders frames `start..end` into numbered images, frames which already
+/// exist on disk are not rendered again, so an interrupted run can be resumed
+pub fn render(opts: &SequenceOptions) -> Result<(), String> {
+    let mut scene = Scene::load(Path::new(&opts.scene)).map_err(|e| e.to_string())?;
+    opts.settings.apply(&mut scene.settings);
+    for w in &scene.warnings {
+        eprintln!("{}", w);
+    }
+    fs::create_dir_all(&opts.out_dir)
+        .map_err(|e| format!("Can't create {}: {}", opts.out_dir.display(), e))?;
+
+    let mut sink = match opts.encoder {
+        Some(ref encoder) => Some(
+            Sink::open(encoder, &scene, opts.fps)
+                .map_err(|e| format!("Can't start encoder: {}", e))?,
+        ),
+        None => None,
+    };
+
+    let mut renderer = Renderer::new(&opts.renderer);
+    let frame_time = 1. / opts.fps;
+    for frame in opts.start..opts.end {
+        let path = frame_path(&opts.out_dir, frame);
+        let pixels = if path.exists() {
+            println!("frame {}: {} exists, skipping", frame, path.display());
+            if sink.is_none() {
+                continue;
+            }
+            let image = read_ppm(&path).map_err(|e| e.to_string())?;
+            if image.width != scene.cam.width || image.height != scene.cam.height {
+                return Err(format!(
+                    "{} doesn't match the camera resolution",
+                    path.display()
+                ));
+            }
+            image.pixels
+        } else {
+            // Previous frame placement is what motion blur interpolates from
+            let t = frame as f32 * frame_time;
+            scene.evaluate_at(t - frame_time);
+            scene.begin_frame();
+            scene.evaluate_at(t);
+
+            // Written aside first so an interrupted run never leaves a partial frame to resume from
+            let pixels = renderer.render(&scene);
+            let partial = path.with_extension("ppm.tmp");
+            write_ppm(&partial, scene.cam.width, scene.cam.height, &pixels)
+                .and_then(|_| fs::rename(&partial, &path))
+                .map_err(|e| format!("Can't write {}: {}", path.display(), e))?;
+            println!("frame {}: {}", frame, path.display());
+            pixels
+        };
+        if let Some(ref mut sink) = sink {
+            sink.write_frame(&pixels)
+                .map_err(|e| format!("Can't encode frame {}: {}", frame, e))?;
+        }
+    }
+
+    match sink {
+        Some(sink) => sink.finish().map_err(|e| e.to_string()),
+        None => Ok(()),
+    }
+}
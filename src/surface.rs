@@ -1,7 +1,5 @@
 use crate::math::Vec3f;
 
-use std::collections::HashSet;
-
 #[derive(Hash, PartialEq, Eq)]
 pub struct Surface {
     pub pixels: Vec<u8>,
@@ -31,7 +29,7 @@ impl Surface {
 
     /// dw -- division quadrant width
     /// dh -- division quadrant height
-    pub fn divide(&self, dw: u32, dh: u32) -> HashSet<Division> {
+    pub fn divide(&self, dw: u32, dh: u32) -> Vec<Division> {
         let mut w_overflow = false;
         let mut h_overflow = false;
         let w_num = self.w / dw
@@ -49,7 +47,7 @@ impl Surface {
                 0
             };
 
-        let mut divisions = HashSet::new();
+        let mut divisions = Vec::new();
         for i in 0..w_num {
             for j in 0..h_num {
                 let w = if w_overflow && i == w_num - 1 {
@@ -64,7 +62,7 @@ impl Surface {
                     dh
                 };
 
-                divisions.insert(Division {
+                divisions.push(Division {
                     x0: i * dw,
                     y0: j * dh,
                     w,
@@ -0,0 +1,467 @@
+use crate::animation::Motion;
+use crate::camera::Projection;
+use crate::geometry::Geometry;
+use crate::keyframe::Property;
+use crate::material::{Material, MaterialRef};
+use crate::math::{Mat4f, Vec3f};
+use crate::scene::Scene;
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// Scene renders, but likely not as intended
+    Warning,
+    /// Scene can't be rendered
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Part of the scene the issue is in, e.g. `object "s1"` or `camera`
+    pub item: String,
+    /// Offending field, e.g. `shapes[0].radius`
+    pub field: String,
+    pub message: String,
+    /// Line of the scene file the item starts at, if it could be found
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        write!(
+            f,
+            "{}: {}, {}: {}",
+            severity, self.item, self.field, self.message
+        )
+    }
+}
+
+struct Checker<'a> {
+    issues: Vec<Issue>,
+    source: &'a str,
+    item: String,
+    line: Option<usize>,
+}
+
+impl<'a> Checker<'a> {
+    /// Following issues are reported against `item`, which is looked up in
+    /// the source text by `key` to find its line. Spaces are ignored and the
+    /// line is only given when exactly one line has the key, items from
+    /// included files or sharing a name with another one get none
+    fn item(&mut self, item: String, key: Option<&str>) {
+        self.line = key.and_then(|k| {
+            let key: String = k.chars().filter(|c| *c != ' ').collect();
+            let mut found = self.source.lines().enumerate().filter(|(_, l)| {
+                l.chars()
+                    .filter(|c| *c != ' ')
+                    .collect::<String>()
+                    .contains(&key)
+            });
+            match (found.next(), found.next()) {
+                (Some((i, _)), None) => Some(i + 1),
+                _ => None,
+            }
+        });
+        self.item = item;
+    }
+
+    fn report(&mut self, severity: Severity, field: &str, message: &str) {
+        self.issues.push(Issue {
+            severity,
+            item: self.item.clone(),
+            field: field.to_string(),
+            message: message.to_string(),
+            line: self.line,
+        });
+    }
+
+    fn error(&mut self, field: &str, message: &str) {
+        self.report(Severity::Error, field, message);
+    }
+
+    fn warning(&mut self, field: &str, message: &str) {
+        self.report(Severity::Warning, field, message);
+    }
+
+    fn finite(&mut self, field: &str, v: &Vec3f) -> bool {
+        if v.iter().all(|c| c.is_finite()) {
+            true
+        } else {
+            self.error(field, "contains NaN or infinity");
+            false
+        }
+    }
+
+    fn direction(&mut self, field: &str, v: &Vec3f) {
+        if !self.finite(field, v) {
+            return;
+        }
+        let len = v.norm();
+        if len == 0. {
+            self.error(field, "zero length direction");
+        } else if (len - 1.).abs() > 1e-3 {
+            self.warning(field, "direction is not normalized");
+        }
+    }
+
+    fn transform(&mut self, field: &str, m: &Mat4f) {
+        if m.iter().any(|c| !c.is_finite()) {
+            self.error(field, "contains NaN or infinity");
+        }
+    }
+
+    /// `prefix` goes before field names, e.g. `mat.`
+    fn material(&mut self, prefix: &str, m: &Material) {
+        self.finite(&format!("{}ambient", prefix), &m.ambient);
+        self.finite(&format!("{}diffuse", prefix), &m.diffuse);
+        self.finite(&format!("{}specular", prefix), &m.specular);
+        if m.refraction < 0. {
+            self.error(
+                &format!("{}refraction", prefix),
+                "negative index of refraction",
+            );
+        }
+        if m.reflection < 0. || m.reflection > 1. {
+            self.warning(
+                &format!("{}reflection", prefix),
+                "should be between 0 and 1",
+            );
+        }
+        if m.shininess < 0. {
+            self.warning(&format!("{}shininess", prefix), "negative shininess");
+        }
+    }
+
+    fn motion(&mut self, field: &str, m: &Motion) {
+        match *m {
+            Motion::Path(ref a) => {
+                if !a.is_consistent() {
+                    self.error(field, "path needs a length for every control point");
+                }
+            }
+            Motion::Orbit(ref o) => {
+                self.finite(&format!("{}.center", field), &o.center);
+                if o.axis.norm() == 0. {
+                    self.error(&format!("{}.axis", field), "zero length direction");
+                }
+            }
+        }
+    }
+}
+
+/// False for NaN as well
+fn positive(x: f32) -> bool {
+    x > 0.
+}
+
+fn shape(c: &mut Checker, field: &str, shape: &Geometry) {
+    match *shape {
+        Geometry::Sphere(s) => {
+            if !positive(s.radius) {
+                c.error(&format!("{}.radius", field), "radius must be positive");
+            }
+        }
+        Geometry::Cuboid(b) => {
+            let f = format!("{}.extent", field);
+            if c.finite(&f, &b.extent) && b.extent.iter().any(|e| *e <= 0.) {
+                c.error(&f, "extent must be positive along every axis");
+            }
+        }
+        Geometry::Triangle(t) => {
+            let ok = c.finite(&format!("{}.a", field), &t.a)
+                & c.finite(&format!("{}.b", field), &t.b)
+                & c.finite(&format!("{}.c", field), &t.c);
+            if ok && (t.b - t.a).cross(&(t.c - t.a)).norm() < 1e-8 {
+                c.error(field, "degenerate triangle");
+            }
+        }
+    }
+}
+
+/// Checks the scene for values the renderer can't handle, `source` is the
+/// text the scene was parsed from and is only used to find line numbers
+pub fn validate(scene: &Scene, source: &str) -> Vec<Issue> {
+    let mut c = Checker {
+        issues: Vec::new(),
+        source,
+        item: String::new(),
+        line: None,
+    };
+
+    let cam = &scene.cam;
+    c.item("camera".to_string(), Some("cam:"));
+    if cam.width == 0 || cam.height == 0 {
+        c.error("width/height", "zero-size image");
+    }
+    c.finite("eye", &cam.eye);
+    c.direction("dir", &cam.dir);
+    c.direction("up", &cam.up);
+    if cam.dir.cross(&cam.up).norm() == 0. {
+        c.error("up", "parallel to the view direction");
+    }
+    match cam.projection {
+        Projection::Perspective | Projection::Cylindrical
+            if !positive(cam.fov) || cam.fov >= 90. =>
+        {
+            c.error("fov", "must be between 0 and 90 degrees");
+        }
+        Projection::Orthographic { height } if !positive(height) => {
+            c.error("projection.height", "must be positive");
+        }
+        _ => {}
+    }
+    if cam.aperture < 0. {
+        c.error("aperture", "negative aperture");
+    }
+    if cam.samples == 0 {
+        c.warning("samples", "zero samples, one is used");
+    }
+    if let Some(ref stereo) = cam.stereo {
+        if !positive(stereo.convergence) {
+            c.error("stereo.convergence", "must be positive");
+        }
+    }
+
+    if let Some(offset) = scene.settings.ray_offset {
+        c.item("settings".to_string(), Some("settings:"));
+        if !offset.is_finite() || offset < 0. {
+            c.error("ray_offset", "must be zero or positive");
+        }
+    }
+
+    for (name, m) in &scene.materials {
+        c.item(
+            format!("material \"{}\"", name),
+            Some(&format!("\"{}\":", name)),
+        );
+        c.material("", m);
+    }
+
+    if scene.objects.is_empty() {
+        c.item("scene".to_string(), None);
+        c.warning("objects", "no objects, only the background is rendered");
+    }
+    for (i, obj) in scene.objects.iter().enumerate() {
+        let key = format!("name: \"{}\"", obj.name);
+        let unique = scene.objects.iter().filter(|o| o.name == obj.name).count() == 1;
+        c.item(
+            format!("object \"{}\"", obj.name),
+            if unique { Some(&key) } else { None },
+        );
+        if scene.objects[..i].iter().any(|o| o.name == obj.name) {
+            c.warning(
+                "name",
+                "duplicate name, tracks and targets will use the first object",
+            );
+        }
+        if obj.shapes.is_empty() && !scene.objects.iter().any(|o| o.parent == Some(i)) {
+            c.warning("shapes", "no shapes, the object is invisible");
+        }
+        for (j, s) in obj.shapes.iter().enumerate() {
+            shape(&mut c, &format!("shapes[{}]", j), s);
+        }
+        c.transform("transform", &obj.transform);
+        match obj.material {
+            MaterialRef::Inline(ref m) => c.material("mat.", m),
+            MaterialRef::Named(ref name) => {
+                if !scene.materials.contains_key(name) {
+                    c.error("mat", &format!("unknown material \"{}\"", name));
+                }
+            }
+        }
+        if let Some(ref a) = obj.animation {
+            if !a.is_consistent() {
+                c.error("animation", "path needs a length for every control point");
+            }
+        }
+    }
+
+    for inst in &scene.instances {
+        let key = format!("name: \"{}\"", inst.name);
+        let unique = scene
+            .instances
+            .iter()
+            .filter(|i| i.name == inst.name)
+            .count()
+            == 1;
+        c.item(
+            format!("instance \"{}\"", inst.name),
+            if unique { Some(&key) } else { None },
+        );
+        match scene.prefabs.get(&inst.prefab) {
+            None => c.error("prefab", &format!("unknown prefab \"{}\"", inst.prefab)),
+            Some(prefab) => {
+                for name in inst.overrides.keys() {
+                    if !prefab.contains(name) {
+                        c.warning("overrides", &format!("prefab has no object \"{}\"", name));
+                    }
+                }
+            }
+        }
+        c.transform("transform", &inst.transform);
+    }
+
+    for (i, light) in scene.lights.iter().enumerate() {
+        // Lights have no name to find them by and may come from included files
+        c.item(format!("light #{}", i), None);
+        c.finite("pos", &light.pos);
+        c.finite("color", &light.color);
+        if let Some(ref m) = light.motion {
+            c.motion("motion", m);
+        }
+    }
+    if scene.lights.is_empty() && !scene.objects.is_empty() {
+        c.item("scene".to_string(), None);
+        c.warning("lights", "no lights, objects are black");
+    }
+
+    if let Some(ref anim) = scene.cam_animation {
+        c.item("camera animation".to_string(), Some("cam_animation:"));
+        if let Some(ref m) = anim.motion {
+            c.motion("motion", m);
+        }
+    }
+
+    for (i, track) in scene.tracks.iter().enumerate() {
+        c.item(format!("track #{}", i), Some("tracks:"));
+        if track.keys.is_empty() {
+            c.warning("keys", "no keyframes");
+        }
+        if track.keys.windows(2).any(|k| k[1].time < k[0].time) {
+            c.error("keys", "keyframes must be sorted by time");
+        }
+        let target = match track.property {
+            Property::Position(ref n)
+            | Property::Rotation(ref n)
+            | Property::Scale(ref n)
+            | Property::Ambient(ref n)
+            | Property::Diffuse(ref n)
+            | Property::Specular(ref n)
+            | Property::Shininess(ref n)
+            | Property::Reflection(ref n) => scene.objects.iter().any(|o| &o.name == n),
+            Property::LightPosition(l) | Property::LightColor(l) => l < scene.lights.len(),
+            Property::CameraEye | Property::CameraFov => true,
+        };
+        if !target {
+            c.warning("property", "refers to a missing object or light");
+        }
+    }
+
+    c.issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENE: &str = r#"(
+    cam: (
+        eye: (0, 0, 60),
+        dir: (0, 0, -1),
+        up: (0, -1, 0),
+        fov: 30,
+        width: 64,
+        height: 64,
+        angles: (x: 0, y: 180),
+    ),
+    objects: [
+        (
+            name: "ball",
+            shapes: [Sphere((radius: 5))],
+            mat: (
+                ambient: (0.1, 0.1, 0.1),
+                diffuse: (0, 1, 0.3),
+                specular: (1, 1, 1),
+                shininess: 10,
+                reflection: 0.5,
+                refraction: 0,
+            ),
+            transform: (1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1),
+        ),
+        (
+            name: "card",
+            shapes: [Triangle((a: (0, 0, 0), b: (10, 0, 0), c: (0, 10, 0)))],
+            mat: (
+                ambient: (0.1, 0.1, 0.1),
+                diffuse: (1, 0, 0),
+                specular: (1, 1, 1),
+                shininess: 10,
+                reflection: 0,
+                refraction: 0,
+            ),
+            transform: (1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, -10, 1),
+        ),
+    ],
+    lights: [(pos: (0, -20, 20), color: (1, 1, 1))],
+)"#;
+
+    fn check(source: &str) -> Vec<Issue> {
+        let scene: Scene = ron::de::from_str(source).unwrap();
+        validate(&scene, source)
+    }
+
+    fn line(source: &str, issue: &Issue) -> String {
+        source
+            .lines()
+            .nth(issue.line.unwrap() - 1)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn valid() {
+        assert!(check(SCENE).is_empty());
+    }
+
+    #[test]
+    fn camera() {
+        let source = SCENE
+            .replace("width: 64", "width: 0")
+            .replace("fov: 30", "fov: 120");
+        let issues = check(&source);
+        assert_eq!(issues.len(), 2);
+        assert!(issues
+            .iter()
+            .all(|i| i.severity == Severity::Error && i.item == "camera"));
+        assert_eq!(issues[0].field, "width/height");
+        assert_eq!(issues[1].field, "fov");
+        assert!(line(&source, &issues[0]).contains("cam:"));
+    }
+
+    #[test]
+    fn shapes() {
+        let source = SCENE
+            .replace("radius: 5", "radius: -1")
+            .replace("b: (10, 0, 0)", "b: (0, 0, 0)");
+        let issues = check(&source);
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].item, "object \"ball\"");
+        assert_eq!(issues[0].field, "shapes[0].radius");
+        assert!(line(&source, &issues[0]).contains("\"ball\""));
+        assert_eq!(issues[1].message, "degenerate triangle");
+        assert!(line(&source, &issues[1]).contains("\"card\""));
+    }
+
+    #[test]
+    fn duplicate_names() {
+        // Names shared by several objects can't be told apart in the source
+        let source = SCENE
+            .replace("name: \"card\"", "name: \"ball\"")
+            .replace("fov: 30", "fov: 120");
+        let issues = check(&source);
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[1].field, "name");
+        assert_eq!(issues[1].line, None);
+        // Other items keep their line
+        assert!(line(&source, &issues[0]).contains("cam:"));
+    }
+}
@@ -0,0 +1,50 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Polls modification times of a set of files
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    interval: Duration,
+    last_check: Instant,
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl FileWatcher {
+    pub fn new(paths: &[PathBuf], interval: Duration) -> FileWatcher {
+        let mut watcher = FileWatcher {
+            files: Vec::new(),
+            interval,
+            last_check: Instant::now(),
+        };
+        watcher.watch(paths);
+        watcher
+    }
+
+    /// Replaces the watched set, current file states become the baseline
+    pub fn watch(&mut self, paths: &[PathBuf]) {
+        self.files = paths.iter().map(|p| (p.clone(), modified(p))).collect();
+    }
+
+    /// True if any of the files changed since the previous call,
+    /// the disk is looked at no more often than the polling interval
+    pub fn changed(&mut self) -> bool {
+        if self.last_check.elapsed() < self.interval {
+            return false;
+        }
+        self.last_check = Instant::now();
+
+        let mut changed = false;
+        for &mut (ref path, ref mut time) in &mut self.files {
+            let current = modified(path);
+            if current != *time {
+                *time = current;
+                changed = true;
+            }
+        }
+        changed
+    }
+}